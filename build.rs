@@ -0,0 +1,24 @@
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+/// Compile the assets into a GResource bundle that gets embedded in the binary
+fn main() {
+    if env::var_os("CARGO_FEATURE_GUI").is_none() {
+        return;
+    }
+
+    let target = Path::new(&env::var("OUT_DIR").unwrap())
+        .join("mprvis.gresource");
+    let status = Command::new("glib-compile-resources")
+        .arg("--sourcedir=assets")
+        .arg(format!("--target={}", target.display()))
+        .arg("assets/mprvis.gresource.xml")
+        .status()
+        .expect("Couldn't run glib-compile-resources, is GLib installed?");
+    assert!(status.success(), "glib-compile-resources failed");
+
+    for file in &["mprvis.gresource.xml", "gui.glade", "gui.css"] {
+        println!("cargo:rerun-if-changed=assets/{}", file);
+    }
+}
@@ -96,7 +96,7 @@ impl<T: WebFetcherExt> FetcherWithCache for T {
 
     fn fetch_uncached(&self, url: &String, tx: mpsc::Sender<mpris::Event>)
                       -> Result<Vec<u8>, String> {
-        println!("Fetching {:?}", url);
+        eprintln!("Fetching {:?}", url);
         let mut handle = Easy::new();
         handle.url(&url).unwrap();
         handle.get(true).unwrap();
@@ -10,15 +10,32 @@ use curl::easy::Easy;
 mod fetcher;
 use self::fetcher::FetcherExt;
 
+/// What to fetch art for
+pub enum Request {
+    /// The currently playing track, streamed as `ArtData`/`ArtDone`
+    Current(mpris::Metadata),
+    /// A track from the track list, sent as one `Thumbnail` event
+    Thumbnail(String, mpris::Metadata),
+}
+
 pub fn start(done_tx: mpsc::Sender<mpris::Event>)
-             -> mpsc::Sender<mpris::Metadata> {
+             -> mpsc::Sender<Request> {
+    // Thumbnails get their own fetcher, so a long track list doesn't hold up
+    // the current track's art
+    let thumbnail_tx = spawn_manager(done_tx.clone(), None);
+    spawn_manager(done_tx, Some(thumbnail_tx))
+}
+
+fn spawn_manager(done_tx: mpsc::Sender<mpris::Event>,
+                 thumbnail_tx: Option<mpsc::Sender<Request>>)
+                 -> mpsc::Sender<Request> {
     let (tmp_tx, tmp_rx) = mpsc::channel();
 
     thread::spawn(move || {
         let (req_tx, req_rx) = mpsc::channel();
         tmp_tx.send(req_tx).unwrap();
 
-        let mut manager = Manager::new(done_tx, req_rx);
+        let mut manager = Manager::new(done_tx, req_rx, thumbnail_tx);
         manager.run();
     });
 
@@ -27,31 +44,65 @@ pub fn start(done_tx: mpsc::Sender<mpris::Event>)
 
 struct Manager {
     done_tx: mpsc::Sender<mpris::Event>,
-    request_rx: mpsc::Receiver<mpris::Metadata>,
+    request_rx: mpsc::Receiver<Request>,
+    /// Where thumbnail requests are passed on to, if not handled here
+    thumbnail_tx: Option<mpsc::Sender<Request>>,
     fetcher: fetcher::Fetcher,
 }
 
 impl Manager {
     pub fn new(done_tx: mpsc::Sender<mpris::Event>,
-               request_rx: mpsc::Receiver<mpris::Metadata>) -> Self {
+               request_rx: mpsc::Receiver<Request>,
+               thumbnail_tx: Option<mpsc::Sender<Request>>) -> Self {
         let fetcher = fetcher::Fetcher::new();
         Manager {
             done_tx,
             request_rx,
+            thumbnail_tx,
             fetcher,
         }
     }
 
     pub fn run(&mut self) {
-        for data in &self.request_rx {
-            match self.fetcher.fetch(&data, self.done_tx.clone()) {
-                Ok(_) => {
-                    self.done_tx.send(mpris::Event::ArtDone(true));
+        for req in &self.request_rx {
+            match req {
+                Request::Current(data) => {
+                    match self.fetcher.fetch(&data, self.done_tx.clone()) {
+                        Ok(_) => {
+                            self.done_tx.send(mpris::Event::ArtDone(true));
+                        },
+                        Err(err) => {
+                            eprintln!("Error while fetching art: {}", err);
+                            self.done_tx.send(mpris::Event::ArtDone(false));
+                        }
+                    }
+                },
+                Request::Thumbnail(id, data) => {
+                    if let Some(ref thumbnail_tx) = self.thumbnail_tx {
+                        if thumbnail_tx.send(Request::Thumbnail(id, data))
+                                       .is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    // Collect the chunks instead of streaming them, so they
+                    // don't get mixed up with the current track's art
+                    let (tx, rx) = mpsc::channel();
+                    if let Err(err) = self.fetcher.fetch(&data, tx) {
+                        eprintln!("Error while fetching thumbnail: {}", err);
+                        continue;
+                    }
+                    let mut art = Vec::new();
+                    for ev in rx {
+                        if let mpris::Event::ArtData(ref chunk) = ev {
+                            art.extend_from_slice(chunk);
+                        }
+                    }
+                    if self.done_tx.send(mpris::Event::Thumbnail(id, art))
+                                  .is_err() {
+                        break;
+                    }
                 },
-                Err(err) => {
-                    eprintln!("Error while fetching art: {}", err);
-                    self.done_tx.send(mpris::Event::ArtDone(false));
-                }
             }
         }
     }
@@ -1,17 +1,241 @@
 extern crate mprvis;
+#[macro_use]
+extern crate clap;
 
+#[cfg(feature = "gui")]
 use std::thread;
+use std::path::PathBuf;
+use std::process;
 use std::sync::mpsc;
 
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use mprvis::config::{self, Config};
+use mprvis::headless::{Escape, Format, Template};
+use mprvis::mpris::{Command, MPRIS};
+
 fn main() {
+    let mut actions = mprvis::remote::ACTIONS.to_vec();
+    actions.push("fullscreen");
+
+    let matches = App::new("mprvis")
+        .about("Visualizes what your MPRIS media player is playing")
+        .arg(Arg::with_name("config")
+             .long("config")
+             .takes_value(true)
+             .value_name("FILE")
+             .help("Read settings from FILE instead of \
+                    ~/.config/mprvis/config.toml"))
+        .arg(Arg::with_name("player")
+             .long("player")
+             .takes_value(true)
+             .value_name("PLAYER")
+             .help("The player to show, like spotify or \
+                    org.mpris.MediaPlayer2.vlc"))
+        .arg(Arg::with_name("headless")
+             .long("headless")
+             .help("Print events to stdout instead of opening a window"))
+        .arg(Arg::with_name("format")
+             .long("format")
+             .takes_value(true)
+             .value_name("FORMAT")
+             .requires("headless")
+             .validator(|f| f.parse::<Format>().map(|_| ()))
+             .help("How events are printed in headless mode: text, json or \
+                    a template like '{artist} – {title} [{position}/{length}]' \
+                    [default: text]"))
+        .arg(Arg::with_name("escape")
+             .long("escape")
+             .takes_value(true)
+             .value_name("ESCAPE")
+             .possible_values(&["none", "pango", "shell"])
+             .requires("format")
+             .help("How values in a --format template are escaped \
+                    [default: none]"))
+        .arg(Arg::with_name("theme")
+             .long("theme")
+             .takes_value(true)
+             .value_name("THEME")
+             .conflicts_with("headless")
+             .help("Style the window with themes/THEME.css from \
+                    ~/.config/mprvis or the data directories"))
+        .arg(Arg::with_name("fullscreen")
+             .long("fullscreen")
+             .conflicts_with("headless")
+             .help("Fill the screen without decorations and hide the cursor, \
+                    e.g. for a wall display"))
+        .arg(Arg::with_name("mini")
+             .long("mini")
+             .conflicts_with("headless")
+             .help("Start with a small always-on-top window instead of the \
+                    main one"))
+        .arg(Arg::with_name("action")
+             .long("action")
+             .takes_value(true)
+             .value_name("ACTION")
+             .possible_values(&actions)
+             .conflicts_with("headless")
+             .help("Activate an action in the running mprvis window"))
+        .subcommand(SubCommand::with_name("play-pause")
+                    .about("Toggle between playing and paused"))
+        .subcommand(SubCommand::with_name("play")
+                    .about("Start playing"))
+        .subcommand(SubCommand::with_name("pause")
+                    .about("Pause playback"))
+        .subcommand(SubCommand::with_name("next")
+                    .about("Skip to the next track"))
+        .subcommand(SubCommand::with_name("previous")
+                    .about("Go back to the previous track"))
+        .subcommand(SubCommand::with_name("seek")
+                    .about("Seek in the current track")
+                    .arg(Arg::with_name("position")
+                         .required(true)
+                         .allow_hyphen_values(true)
+                         .validator(|p| parse_seek(&p).map(|_| ()))
+                         .help("Seconds to seek by like +10 or -5, or a \
+                                position like 90 or 1:30")))
+        .subcommand(SubCommand::with_name("status")
+                    .about("Print what the player is playing")
+                    .arg(Arg::with_name("format")
+                         .long("format")
+                         .takes_value(true)
+                         .value_name("TEMPLATE")
+                         .default_value("{status}: {artist} - {title}\
+                                         {? [{position}/{length}]}")
+                         .validator(|f| f.parse::<Template>().map(|_| ()))
+                         .help("Template for the output, see --format")))
+        .get_matches();
+
+    let mut source = config::Source::new(
+        matches.value_of_os("config").map(PathBuf::from),
+        config::Args {
+            player: matches.value_of("player").map(|p| p.to_string()),
+            theme: matches.value_of("theme").map(|t| t.to_string()),
+            fullscreen: matches.is_present("fullscreen"),
+            mini: matches.is_present("mini"),
+        },
+    );
+    // Acting on the player once hardly needs the config, so don't refuse to
+    // because of a typo in it
+    let once = matches.subcommand_name().is_some()
+        || matches.is_present("action");
+    let config = source.load().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        if !once {
+            process::exit(1);
+        }
+        eprintln!("Using the default settings");
+        source.defaults()
+    });
+
+    if let (name, Some(sub)) = matches.subcommand() {
+        run_subcommand(name, sub, &config);
+    } else if let Some(action) = matches.value_of("action") {
+        if let Err(err) = mprvis::remote::activate(action) {
+            eprintln!("Couldn't activate {}: {}", action,
+                      err.message().unwrap_or("Unknown error"));
+            process::exit(1);
+        }
+    } else if matches.is_present("headless") {
+        // Both are validated by clap if they're given
+        let mut format = value_t!(matches, "format", Format)
+            .unwrap_or(Format::Text);
+        match format {
+            Format::Template(ref mut template) => {
+                template.escape = value_t!(matches, "escape", Escape)
+                    .unwrap_or(Escape::None);
+            },
+            _ if matches.is_present("escape") => {
+                clap::Error::with_description(
+                    "--escape can only be used with a --format template",
+                    clap::ErrorKind::ArgumentConflict
+                ).exit();
+            },
+            _ => {},
+        }
+        run_headless(format, &config);
+    } else {
+        run_gui(source, config);
+    }
+}
+
+/// Act on the player once and exit
+fn run_subcommand(name: &str, matches: &ArgMatches, config: &Config) {
+    let mpris = MPRIS::connect(config.player_name()).unwrap_or_else(|err| {
+        eprintln!("Couldn't connect to the player: {}",
+                  err.message().unwrap_or("Unknown error"));
+        process::exit(1);
+    });
+
+    let cmd = match name {
+        "play-pause" => Command::PlayPause,
+        "play" => Command::Play,
+        "pause" => Command::Pause,
+        "next" => Command::Next,
+        "previous" => Command::Previous,
+        "seek" => parse_seek(matches.value_of("position").unwrap()).unwrap(),
+        "status" => {
+            let template = value_t!(matches, "format", Template)
+                .unwrap_or_else(|e| e.exit());
+            if let Err(err) = mprvis::headless::print_status(&mpris,
+                                                             &template) {
+                eprintln!("Couldn't get the status: {}",
+                          err.message().unwrap_or("Unknown error"));
+                process::exit(1);
+            }
+            return;
+        },
+        _ => unreachable!(),
+    };
+    if let Err(err) = mpris.execute(&cmd) {
+        eprintln!("Couldn't send {:?}: {}", cmd,
+                  err.message().unwrap_or("Unknown error"));
+        process::exit(1);
+    }
+}
+
+/// Parse `+10`/`-10` as a relative seek and `90` or `1:30` as a position,
+/// in seconds
+fn parse_seek(s: &str) -> Result<Command, String> {
+    let invalid = || format!("Invalid position {:?}", s);
+    let micros = |secs: f64| (secs * 1e6) as i64;
+    if s.starts_with('+') || s.starts_with('-') {
+        return s.parse::<f64>().map(|o| Command::Seek(micros(o)))
+                .map_err(|_| invalid());
+    }
+
+    let mut secs = 0.0;
+    for part in s.split(':') {
+        secs = secs * 60.0 + part.parse::<f64>().map_err(|_| invalid())?;
+    }
+    Ok(Command::SetPosition(micros(secs)))
+}
+
+fn run_headless(format: Format, config: &Config) {
+    let (events_tx, events_rx) = mpsc::channel();
+    let (_commands_tx, commands_rx) = mpsc::channel();
+    mprvis::mpris::MPRIS::start(events_tx, commands_rx, config.player_name(),
+                                format.uses_art());
+    mprvis::headless::run(events_rx, format, config);
+}
+
+#[cfg(feature = "gui")]
+fn run_gui(source: config::Source, config: Config) {
     let (tx, rx) = mpsc::channel();
+    let (commands_tx, commands_rx) = mpsc::channel();
+    let player = config.player_name();
     let gui = thread::spawn(|| {
-        unsafe { mprvis::gui::start(tx); }
+        unsafe { mprvis::gui::start(tx, commands_tx, source, config); }
     });
 
-    let events_tx = rx.recv().unwrap();
+    // If mprvis is already running, GTK activates that instance instead and
+    // our GUI never starts
+    let events_tx = match rx.recv() {
+        Ok(events_tx) => events_tx,
+        Err(_) => return,
+    };
     let mpris = thread::spawn(move || {
-        mprvis::mpris::MPRIS::start(events_tx);
+        mprvis::mpris::MPRIS::start(events_tx, commands_rx, player, true);
     });
 
     gui.join().unwrap_or_else(|err| {
@@ -21,3 +245,32 @@ fn main() {
         eprintln!("MPRIS panicked: {:?}", err);
     })
 }
+
+#[cfg(not(feature = "gui"))]
+fn run_gui(_source: config::Source, config: Config) {
+    eprintln!("mprvis was built without the GUI, running headless");
+    run_headless(Format::Text, &config);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_seek_positions() {
+        match parse_seek("+10") {
+            Ok(Command::Seek(10_000_000)) => {},
+            other => panic!("{:?}", other),
+        }
+        match parse_seek("-2.5") {
+            Ok(Command::Seek(-2_500_000)) => {},
+            other => panic!("{:?}", other),
+        }
+        match parse_seek("1:30") {
+            Ok(Command::SetPosition(90_000_000)) => {},
+            other => panic!("{:?}", other),
+        }
+        assert!(parse_seek("soon").is_err());
+        assert!(parse_seek("1:").is_err());
+    }
+}
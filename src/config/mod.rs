@@ -0,0 +1,785 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::de::{self, Deserialize, Deserializer};
+use toml;
+
+use xdg;
+
+mod polled;
+pub use self::polled::PolledFile;
+
+/// The settings from `$XDG_CONFIG_HOME/mprvis/config.toml`. The GUI applies
+/// changes to the file while it's running, headless mode only reads it once.
+///
+/// ```toml
+/// player = "spotify"
+/// theme = "wall"
+/// layout = "side"
+///
+/// [assets]
+/// dir = "/opt/mprvis/assets"
+///
+/// [cover]
+/// overlay_opacity = 0.3
+/// dynamic_colors = true
+/// background = "blur"
+///
+/// [font]
+/// family = "Cantarell Light"
+/// size = 20.0
+/// title_size = 32.0
+///
+/// [featured]
+/// show = "line"
+/// last_separator = " and "
+/// limit = 2
+///
+/// [labels]
+/// title = "marquee"
+/// artist = "shrink"
+///
+/// [cache]
+/// art = 100
+///
+/// [lyrics]
+/// enabled = true
+/// dir = "/srv/lyrics"
+///
+/// [kiosk]
+/// enabled = true
+/// monitor = "HDMI-1"
+/// hide_cursor = 3
+/// inhibit_idle = true
+///
+/// [mini]
+/// enabled = false
+/// opacity = 0.6
+///
+/// [notifications]
+/// enabled = true
+///
+/// [tray]
+/// enabled = true
+/// start_hidden = false
+///
+/// [keys]
+/// play_pause = "space"
+/// next = "<Shift>Right"
+/// quit = "<Control>q"
+/// ```
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The player to show, either its full bus name or the part after
+    /// `org.mpris.MediaPlayer2.`
+    pub player: Option<String>,
+    /// A stylesheet from `themes/` in the asset directories
+    pub theme: Option<String>,
+    pub layout: Layout,
+    pub assets: Assets,
+    pub cover: Cover,
+    pub font: Font,
+    pub featured: Featured,
+    pub labels: Labels,
+    pub cache: Cache,
+    pub lyrics: Lyrics,
+    pub kiosk: Kiosk,
+    pub mini: Mini,
+    pub notifications: Notifications,
+    pub tray: Tray,
+    pub keys: Keys,
+}
+
+/// How the window is arranged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize,
+         Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// The labels and controls on top of the cover
+    #[default]
+    Overlay,
+    /// Only the cover
+    Cover,
+    /// A thin strip with a thumbnail, for docking at a screen edge
+    Compact,
+    /// The cover on the left, the labels and controls on the right
+    Side,
+}
+
+impl Layout {
+    pub const ALL : &'static [Layout] = &[
+        Layout::Overlay, Layout::Cover, Layout::Compact, Layout::Side,
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Assets {
+    /// Searched for `gui.glade`, `gui.css` and themes before the usual
+    /// directories
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cover {
+    /// How much the cover is darkened behind the text. With dynamic colors,
+    /// this is the minimum.
+    pub overlay_opacity: Fraction,
+    /// Pick the background and text colors from the cover
+    pub dynamic_colors: bool,
+    /// What fills the window around the cover
+    pub background: Background,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Background {
+    /// A single color
+    #[default]
+    Plain,
+    /// A blurred copy of the cover
+    Blur,
+}
+
+impl Default for Cover {
+    fn default() -> Self {
+        Cover {
+            overlay_opacity: Fraction(0.5),
+            dynamic_colors: true,
+            background: Background::Plain,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Font {
+    pub family: Option<String>,
+    /// In points
+    pub size: Option<Positive>,
+    /// In points
+    pub title_size: Option<Positive>,
+}
+
+/// How the artists featured on a track are shown
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Featured {
+    pub show: ShowFeatured,
+    /// Between the names
+    pub separator: String,
+    /// Before the last name instead of `separator`
+    pub last_separator: String,
+    /// How many names are listed before the others are counted as "and 3
+    /// more". 0 lists all of them.
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShowFeatured {
+    /// "feat. …" after the artist
+    Inline,
+    /// "feat. …" on a line of its own below the artist
+    Line,
+    Hidden,
+}
+
+impl Default for Featured {
+    fn default() -> Self {
+        Featured {
+            show: ShowFeatured::Inline,
+            separator: ", ".to_string(),
+            last_separator: " & ".to_string(),
+            limit: 3,
+        }
+    }
+}
+
+impl Featured {
+    /// Join `names` like "A, B & C", or "A, B, C and 2 more" past the limit
+    pub fn join(&self, names: &[String]) -> String {
+        let shown = if self.limit == 0 {
+            names.len()
+        } else {
+            names.len().min(self.limit)
+        };
+        let (shown, rest) = names.split_at(shown);
+        let mut joined = String::new();
+        for (i, name) in shown.iter().enumerate() {
+            if i + 1 == shown.len() && i > 0 && rest.is_empty() {
+                joined.push_str(&self.last_separator);
+            } else if i > 0 {
+                joined.push_str(&self.separator);
+            }
+            joined.push_str(name);
+        }
+        if !rest.is_empty() {
+            joined.push_str(&format!(" and {} more", rest.len()));
+        }
+        joined
+    }
+}
+
+/// What each label does with text that doesn't fit on one line
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Labels {
+    pub title: Overflow,
+    pub artist: Overflow,
+    pub album: Overflow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overflow {
+    /// Break it into more lines
+    #[default]
+    Wrap,
+    /// Scroll it through on one line
+    Marquee,
+    /// Make the font smaller, down to half its size
+    Shrink,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cache {
+    /// How many covers are kept in the art cache of headless mode on disk.
+    /// The covers that were fetched are also kept in memory while mprvis is
+    /// running, which this doesn't limit.
+    pub art: usize,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache {
+            art: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Lyrics {
+    /// Show the lyrics below the labels, synced to the playback position if
+    /// they're in LRC format
+    pub enabled: bool,
+    /// Searched for `Artist - Title.lrc`, and for LRC files named like local
+    /// tracks, when there's none next to the track
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Kiosk {
+    /// Fill a monitor without decorations, e.g. on a wall display
+    pub enabled: bool,
+    /// The monitor to fill, otherwise the one the window opens on
+    pub monitor: Option<Monitor>,
+    /// Seconds without mouse movement before the cursor is hidden
+    pub hide_cursor: u32,
+    /// Keep the screen from blanking while playing
+    pub inhibit_idle: bool,
+}
+
+impl Default for Kiosk {
+    fn default() -> Self {
+        Kiosk {
+            enabled: false,
+            monitor: None,
+            hide_cursor: 3,
+            inhibit_idle: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Mini {
+    /// Show a small always-on-top window instead of the main one
+    pub enabled: bool,
+    /// How opaque the mini window is while the mouse isn't over it
+    pub opacity: Fraction,
+}
+
+impl Default for Mini {
+    fn default() -> Self {
+        Mini {
+            enabled: false,
+            opacity: Fraction(0.6),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Notifications {
+    /// Show a desktop notification when another track starts playing
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tray {
+    /// Show an icon in the system tray, and hide the window there when it's
+    /// closed
+    pub enabled: bool,
+    /// Start with only the icon
+    pub start_hidden: bool,
+}
+
+/// Key bindings in the window, in the format of `gtk_accelerator_parse` like
+/// `"<Control>q"`. An empty string turns a binding off.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keys {
+    pub play_pause: String,
+    pub seek_forward: String,
+    pub seek_backward: String,
+    pub next: String,
+    pub previous: String,
+    pub fullscreen: String,
+    pub volume_up: String,
+    pub volume_down: String,
+    pub next_player: String,
+    pub quit: String,
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        Keys {
+            play_pause: "space".to_string(),
+            seek_forward: "Right".to_string(),
+            seek_backward: "Left".to_string(),
+            next: "<Shift>Right".to_string(),
+            previous: "<Shift>Left".to_string(),
+            fullscreen: "f".to_string(),
+            volume_up: "plus".to_string(),
+            volume_down: "minus".to_string(),
+            next_player: "p".to_string(),
+            quit: "q".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Monitor {
+    /// The monitor's number, starting at 0
+    Index(i32),
+    /// The connector, like "HDMI-1"
+    Name(String),
+}
+
+/// A number between 0 and 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fraction(pub f64);
+
+impl<'de> Deserialize<'de> for Fraction {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let value = f64::deserialize(d)?;
+        if (0. ..=1.).contains(&value) {
+            Ok(Fraction(value))
+        } else {
+            Err(de::Error::custom(
+                format!("expected a number between 0 and 1, found {}", value)
+            ))
+        }
+    }
+}
+
+/// A number greater than 0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Positive(pub f64);
+
+impl<'de> Deserialize<'de> for Positive {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let value = f64::deserialize(d)?;
+        if value > 0. {
+            Ok(Positive(value))
+        } else {
+            Err(de::Error::custom(
+                format!("expected a number greater than 0, found {}", value)
+            ))
+        }
+    }
+}
+
+/// Options from the command line, which take precedence over the file
+#[derive(Debug, Clone, Default)]
+pub struct Args {
+    pub player: Option<String>,
+    pub theme: Option<String>,
+    /// Turns on kiosk mode
+    pub fullscreen: bool,
+    /// Starts with the mini window
+    pub mini: bool,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    /// An invalid file, with the (1-based) line and column if known
+    Parse(PathBuf, Option<(usize, usize)>, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref path, ref err) =>
+                write!(f, "{}: {}", path.display(), err),
+            Error::Parse(ref path, Some((line, col)), ref msg) =>
+                write!(f, "{}:{}:{}: {}", path.display(), line, col, msg),
+            Error::Parse(ref path, None, ref msg) =>
+                write!(f, "{}: {}", path.display(), msg),
+        }
+    }
+}
+
+impl Config {
+    const PLAYER_PREFIX : &'static str = "org.mpris.MediaPlayer2.";
+
+    /// `$XDG_CONFIG_HOME/mprvis/config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        xdg::config_dir().map(|config| config.join("config.toml"))
+    }
+
+    pub fn parse(path: &Path, contents: &str) -> Result<Self, Error> {
+        toml::from_str(contents).map_err(|err| {
+            // toml appends the position to the message itself
+            let msg = err.to_string();
+            let msg = match msg.rfind(" at line ") {
+                Some(i) => msg[..i].to_string(),
+                None => msg,
+            };
+            // Invalid values only name their key, so look for it ourselves
+            let position = err.line_col()
+                              .map(|(line, col)| (line + 1, col + 1))
+                              .or_else(|| {
+                                  Self::error_key(&msg).and_then(|key| {
+                                      Self::find_key(contents, &key)
+                                  })
+                              });
+            Error::Parse(path.to_path_buf(), position, msg)
+        })
+    }
+
+    /// The key an error message is about. Messages end in
+    /// "for key `table.key`", and unknown fields are named at the start.
+    fn error_key(msg: &str) -> Option<String> {
+        fn quoted(s: &str) -> Option<&str> {
+            let start = s.find('`')? + 1;
+            let len = s[start..].find('`')?;
+            Some(&s[start..start + len])
+        }
+
+        let key = msg.rfind("for key `").and_then(|i| quoted(&msg[i..]));
+        let unknown = if msg.starts_with("unknown field `") {
+            quoted(msg)
+        } else {
+            None
+        };
+        match (key, unknown) {
+            (Some(key), Some(field)) => Some(format!("{}.{}", key, field)),
+            (key, field) => key.or(field).map(|k| k.to_string()),
+        }
+    }
+
+    /// The (1-based) line and column of the value of a dotted `key` like
+    /// `cover.overlay_opacity`, or of the table header if `key` is a table.
+    /// Only understands `[table]` headers and
+    /// `key = value` lines, which is all our schema needs.
+    fn find_key(contents: &str, key: &str) -> Option<(usize, usize)> {
+        let (table, name) = match key.rfind('.') {
+            Some(i) => (&key[..i], &key[i + 1..]),
+            None => ("", key),
+        };
+        let mut current = "";
+        for (i, line) in contents.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') {
+                current = trimmed.trim_matches(|c| c == '[' || c == ']')
+                                 .trim();
+                if current == key {
+                    return Some((i + 1, line.find('[')? + 2));
+                }
+            } else if current == table {
+                let mut parts = trimmed.splitn(2, '=');
+                if parts.next().map(|k| k.trim()) == Some(name) {
+                    let value = line.find('=')? + 1;
+                    let offset = line[value..].len()
+                               - line[value..].trim_start().len();
+                    return Some((i + 1, value + offset + 1));
+                }
+            }
+        }
+        None
+    }
+
+    /// Read the file at `path`. A missing file gives the default settings.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::parse(path, &contents),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound =>
+                Ok(Config::default()),
+            Err(err) => Err(Error::Io(path.to_path_buf(), err)),
+        }
+    }
+
+    pub fn merge(mut self, args: &Args) -> Self {
+        if args.player.is_some() {
+            self.player = args.player.clone();
+        }
+        if args.theme.is_some() {
+            self.theme = args.theme.clone();
+        }
+        if args.fullscreen {
+            self.kiosk.enabled = true;
+        }
+        if args.mini {
+            self.mini.enabled = true;
+        }
+        self
+    }
+
+    /// The full bus name of the configured player
+    pub fn player_name(&self) -> Option<String> {
+        self.player.as_ref().map(|player| {
+            if player.starts_with(Self::PLAYER_PREFIX) {
+                player.clone()
+            } else {
+                format!("{}{}", Self::PLAYER_PREFIX, player)
+            }
+        })
+    }
+}
+
+/// A config file together with the command line options, which can be
+/// reloaded when the file changes
+pub struct Source {
+    file: PolledFile,
+    args: Args,
+}
+
+impl Source {
+    /// Read from `path`, or the default location if it's `None`
+    pub fn new(path: Option<PathBuf>, args: Args) -> Self {
+        Source {
+            file: PolledFile::new(path.or_else(Config::default_path)),
+            args,
+        }
+    }
+
+    pub fn load(&mut self) -> Result<Config, Error> {
+        self.file.loading();
+        let config = match self.file.path() {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+        Ok(config.merge(&self.args))
+    }
+
+    /// Whether the file changed since it was last loaded
+    pub fn changed(&self) -> bool {
+        self.file.changed()
+    }
+
+    /// The default settings with the command line options, for when the
+    /// file can't be loaded
+    pub fn defaults(&self) -> Config {
+        Config::default().merge(&self.args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> Result<Config, Error> {
+        Config::parse(Path::new("config.toml"), contents)
+    }
+
+    #[test]
+    fn it_parses_all_settings() {
+        let config = parse(
+            "player = \"vlc\"\n\
+             theme = \"wall\"\n\
+             layout = \"compact\"\n\
+             [assets]\n\
+             dir = \"/opt/mprvis\"\n\
+             [cover]\n\
+             overlay_opacity = 0.25\n\
+             dynamic_colors = false\n\
+             background = \"blur\"\n\
+             [font]\n\
+             family = \"Cantarell Light\"\n\
+             size = 20.0\n\
+             title_size = 32.0\n\
+             [featured]\n\
+             show = \"line\"\n\
+             separator = \" / \"\n\
+             limit = 0\n\
+             [labels]\n\
+             title = \"marquee\"\n\
+             album = \"shrink\"\n\
+             [cache]\n\
+             art = 5\n\
+             [lyrics]\n\
+             enabled = true\n\
+             dir = \"/srv/lyrics\"\n\
+             [kiosk]\n\
+             enabled = true\n\
+             monitor = 1\n\
+             hide_cursor = 10\n\
+             inhibit_idle = false\n\
+             [mini]\n\
+             enabled = true\n\
+             opacity = 0.8\n\
+             [notifications]\n\
+             enabled = true\n\
+             [tray]\n\
+             enabled = true\n\
+             start_hidden = true\n\
+             [keys]\n\
+             quit = \"<Control>q\"\n\
+             next_player = \"\"\n"
+        ).unwrap();
+        assert_eq!(Config {
+            player: Some("vlc".to_string()),
+            theme: Some("wall".to_string()),
+            layout: Layout::Compact,
+            assets: Assets {
+                dir: Some(PathBuf::from("/opt/mprvis")),
+            },
+            cover: Cover {
+                overlay_opacity: Fraction(0.25),
+                dynamic_colors: false,
+                background: Background::Blur,
+            },
+            font: Font {
+                family: Some("Cantarell Light".to_string()),
+                size: Some(Positive(20.)),
+                title_size: Some(Positive(32.)),
+            },
+            featured: Featured {
+                show: ShowFeatured::Line,
+                separator: " / ".to_string(),
+                limit: 0,
+                ..Featured::default()
+            },
+            labels: Labels {
+                title: Overflow::Marquee,
+                artist: Overflow::Wrap,
+                album: Overflow::Shrink,
+            },
+            cache: Cache {
+                art: 5,
+            },
+            lyrics: Lyrics {
+                enabled: true,
+                dir: Some(PathBuf::from("/srv/lyrics")),
+            },
+            kiosk: Kiosk {
+                enabled: true,
+                monitor: Some(Monitor::Index(1)),
+                hide_cursor: 10,
+                inhibit_idle: false,
+            },
+            mini: Mini {
+                enabled: true,
+                opacity: Fraction(0.8),
+            },
+            notifications: Notifications {
+                enabled: true,
+            },
+            tray: Tray {
+                enabled: true,
+                start_hidden: true,
+            },
+            keys: Keys {
+                quit: "<Control>q".to_string(),
+                next_player: "".to_string(),
+                ..Keys::default()
+            },
+        }, config);
+        assert_eq!(Some("org.mpris.MediaPlayer2.vlc".to_string()),
+                   config.player_name());
+    }
+
+    #[test]
+    fn it_joins_featured_artists() {
+        let names : Vec<String> = ["Aretha", "Ella", "Nina", "Billie", "Etta"]
+            .iter().map(|name| name.to_string()).collect();
+        let featured = Featured::default();
+        assert_eq!("", featured.join(&[]));
+        assert_eq!("Aretha", featured.join(&names[..1]));
+        assert_eq!("Aretha & Ella", featured.join(&names[..2]));
+        assert_eq!("Aretha, Ella & Nina", featured.join(&names[..3]));
+        assert_eq!("Aretha, Ella, Nina and 2 more", featured.join(&names));
+
+        let featured = Featured { limit: 0, ..Featured::default() };
+        assert_eq!("Aretha, Ella, Nina, Billie & Etta", featured.join(&names));
+    }
+
+    #[test]
+    fn it_defaults_missing_settings() {
+        let config = parse("[cover]\n").unwrap();
+        assert_eq!(Config::default(), config);
+        assert_eq!(Fraction(0.5), config.cover.overlay_opacity);
+        assert_eq!(None, config.player_name());
+    }
+
+    #[test]
+    fn it_reports_error_positions() {
+        let err = parse("player = \"vlc\"\n[cover]\noverlay_opacity = 2.0\n")
+            .unwrap_err();
+        assert_eq!("config.toml:3:19: expected a number between 0 and 1, \
+                    found 2 for key `cover.overlay_opacity`",
+                   err.to_string());
+
+        let err = parse("[font]\nfamliy = \"Sans\"\n").unwrap_err();
+        match err {
+            Error::Parse(_, Some((2, _)), ref msg) =>
+                assert!(msg.starts_with("unknown field `famliy`"), "{}", msg),
+            _ => panic!("{}", err),
+        }
+
+        let err = parse("[cover]\n[nope]\n").unwrap_err();
+        match err {
+            Error::Parse(_, Some((2, 2)), _) => {},
+            _ => panic!("{}", err),
+        }
+
+        let err = parse("theme = \n").unwrap_err();
+        match err {
+            Error::Parse(_, Some((1, _)), _) => {},
+            _ => panic!("{}", err),
+        }
+    }
+
+    #[test]
+    fn it_lets_args_override_the_file() {
+        let config = parse("player = \"vlc\"\ntheme = \"wall\"\n").unwrap();
+        let config = config.merge(&Args {
+            player: Some("org.mpris.MediaPlayer2.spotify".to_string()),
+            theme: None,
+            fullscreen: true,
+            mini: true,
+        });
+        assert_eq!(Some("org.mpris.MediaPlayer2.spotify".to_string()),
+                   config.player_name());
+        assert_eq!(Some("wall".to_string()), config.theme);
+        assert!(config.kiosk.enabled);
+        assert!(config.mini.enabled);
+
+        let config = parse("[kiosk]\nmonitor = \"HDMI-1\"\n").unwrap();
+        assert_eq!(Some(Monitor::Name("HDMI-1".to_string())),
+                   config.kiosk.monitor);
+        assert!(!config.merge(&Args::default()).kiosk.enabled);
+    }
+}
@@ -0,0 +1,45 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A file that's reloaded when it changes. gio 0.4 doesn't bind
+/// `GFileMonitor`, so its modification time is polled instead.
+pub struct PolledFile {
+    path: Option<PathBuf>,
+    /// When the file was modified at the time it was last loaded
+    modified: Option<SystemTime>,
+}
+
+impl PolledFile {
+    /// How often polled files are checked for changes, in seconds
+    pub const INTERVAL : u32 = 1;
+
+    pub fn new(path: Option<PathBuf>) -> Self {
+        PolledFile {
+            path,
+            modified: None,
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        self.path.as_ref()
+                 .and_then(|path| fs::metadata(path).ok())
+                 .and_then(|meta| meta.modified().ok())
+    }
+
+    /// Note that the file is being loaded. `false` if it doesn't exist.
+    pub fn loading(&mut self) -> bool {
+        self.modified = self.modified();
+        self.modified.is_some()
+    }
+
+    /// Whether the file changed, appeared or disappeared since it was last
+    /// loaded
+    pub fn changed(&self) -> bool {
+        self.modified() != self.modified
+    }
+}
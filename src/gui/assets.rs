@@ -0,0 +1,128 @@
+use std::path::{Path, PathBuf};
+
+use gtk;
+use gtk::prelude::*;
+use gio;
+use glib;
+
+/// Where the assets live in the embedded GResource
+const RESOURCE_PREFIX : &'static str = "/space/jazzpis/mprvis/";
+
+static RESOURCE : &'static [u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/mprvis.gresource"));
+
+/// Make the embedded assets available. Call this once before loading any.
+pub fn register() {
+    match gio::Resource::new_from_data(&glib::Bytes::from_static(RESOURCE)) {
+        Ok(resource) => gio::resources_register(&resource),
+        Err(err) => eprintln!("Couldn't load embedded assets: {}", err),
+    }
+}
+
+/// Directories that can override the embedded assets, most important first:
+/// `dir` from the config, `$XDG_CONFIG_HOME/mprvis`, then `mprvis` in
+/// `$XDG_DATA_HOME` and `$XDG_DATA_DIRS`
+fn override_dirs(dir: Option<&Path>) -> Vec<PathBuf> {
+    let xdg = glib::get_user_config_dir().into_iter()
+        .chain(glib::get_user_data_dir())
+        .chain(glib::get_system_data_dirs())
+        .map(|dir| dir.join("mprvis"));
+    dir.map(Path::to_path_buf).into_iter().chain(xdg).collect()
+}
+
+/// The first `name` in `dirs` that exists
+fn find_in<P: AsRef<Path>>(name: &str, dirs: &[P]) -> Option<PathBuf> {
+    dirs.iter()
+        .map(|dir| dir.as_ref().join(name))
+        .find(|path| path.is_file())
+}
+
+/// The file overriding the asset `name`, if there is one. `dir` is searched
+/// first.
+pub fn find(name: &str, dir: Option<&Path>) -> Option<PathBuf> {
+    find_in(name, &override_dirs(dir))
+}
+
+/// A builder for the UI definition `name`. An override is only used if it
+/// loads and has every object of the embedded definition, so it can't crash
+/// the GUI once we look those up.
+pub fn builder(name: &str, dir: Option<&Path>) -> gtk::Builder {
+    let embedded = gtk::Builder::new_from_resource(
+        &format!("{}{}", RESOURCE_PREFIX, name)
+    );
+    if let Some(path) = find(name, dir) {
+        match load_override(&path, &embedded) {
+            Ok(builder) => return builder,
+            Err(err) => eprintln!("Ignoring {}: {}", path.display(), err),
+        }
+    }
+    embedded
+}
+
+fn load_override(path: &Path, embedded: &gtk::Builder)
+                 -> Result<gtk::Builder, String> {
+    let builder = gtk::Builder::new();
+    builder.add_from_file(path).map_err(|err| err.to_string())?;
+
+    for object in embedded.get_objects() {
+        let expected = object.get_type();
+        let id = match object.dynamic_cast::<gtk::Buildable>() {
+            Ok(buildable) => BuildableExtManual::get_name(&buildable),
+            Err(_) => None,
+        };
+        // Objects without an ID get generated ones, those aren't looked up
+        let id = match id {
+            Some(ref id) if !id.starts_with("___object_") => id,
+            _ => continue,
+        };
+        match builder.get_object::<glib::Object>(id) {
+            Some(ref object) if object.get_type().is_a(&expected) => {},
+            Some(_) => return Err(format!("{} isn't a {}", id, expected)),
+            None => return Err(format!("{} is missing", id)),
+        }
+    }
+    Ok(builder)
+}
+
+/// Load the stylesheet `name` into `provider`
+pub fn load_css(provider: &gtk::CssProvider, name: &str, dir: Option<&Path>)
+                -> Result<(), glib::Error> {
+    match find(name, dir) {
+        Some(path) => provider.load_from_path(&path.to_string_lossy()),
+        None => {
+            let data = gio::resources_lookup_data(
+                &format!("{}{}", RESOURCE_PREFIX, name),
+                gio::ResourceLookupFlags::empty()
+            )?;
+            provider.load_from_data(&data)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn it_prefers_earlier_override_dirs() {
+        let root = env::temp_dir()
+            .join(format!("mprvis-assets-test-{}", process::id()));
+        let (user, system) = (root.join("user"), root.join("system"));
+        fs::create_dir_all(&user).unwrap();
+        fs::create_dir_all(&system).unwrap();
+        fs::write(user.join("gui.css"), "").unwrap();
+        fs::write(system.join("gui.css"), "").unwrap();
+        fs::write(system.join("gui.glade"), "").unwrap();
+
+        let dirs = [&user, &system];
+        assert_eq!(Some(user.join("gui.css")), find_in("gui.css", &dirs));
+        assert_eq!(Some(system.join("gui.glade")), find_in("gui.glade", &dirs));
+        assert_eq!(None, find_in("missing.css", &dirs));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
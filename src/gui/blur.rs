@@ -0,0 +1,93 @@
+use gdk_pixbuf;
+use gdk_pixbuf::prelude::*;
+
+/// The size the cover is scaled down to before blurring. The blur hides the
+/// missing detail when it's scaled back up.
+pub const SIZE : i32 = 128;
+/// The standard deviation of the blur, in pixels at `SIZE`
+const SIGMA : f64 = 4.;
+
+/// A small, blurred copy of `pixbuf` to stretch over the window
+pub fn blurred(pixbuf: &gdk_pixbuf::Pixbuf) -> Option<gdk_pixbuf::Pixbuf> {
+    let small = pixbuf.scale_simple(SIZE, SIZE,
+                                    gdk_pixbuf::InterpType::Bilinear)?;
+    let (width, height) = (small.get_width() as usize,
+                           small.get_height() as usize);
+    let stride = small.get_rowstride() as usize;
+    let channels = small.get_n_channels() as usize;
+    gaussian(unsafe { small.get_pixels() }, width, height, stride, channels,
+             SIGMA);
+    Some(small)
+}
+
+/// Blur `pixels`, laid out like a `Pixbuf`, in place. Pixels beyond the edges
+/// are taken to be the same as the nearest edge.
+fn gaussian(pixels: &mut [u8], width: usize, height: usize, stride: usize,
+            channels: usize, sigma: f64) {
+    let radius = (sigma * 3.).ceil() as isize;
+    let mut kernel : Vec<_> = (-radius..radius + 1)
+        .map(|i| (-(i * i) as f64 / (2. * sigma * sigma)).exp())
+        .collect();
+    let sum : f64 = kernel.iter().sum();
+    for weight in &mut kernel {
+        *weight /= sum;
+    }
+
+    let clamp = |i: isize, len: usize| i.max(0).min(len as isize - 1) as usize;
+    // Horizontally into `rows`, then vertically back into `pixels`
+    let mut rows = vec![0f64; width * height * channels];
+    for y in 0..height {
+        for x in 0..width {
+            for c in 0..channels {
+                rows[(y * width + x) * channels + c] = kernel.iter()
+                    .enumerate()
+                    .map(|(k, weight)| {
+                        let sx = clamp(x as isize + k as isize - radius, width);
+                        weight * pixels[y * stride + sx * channels + c] as f64
+                    })
+                    .sum();
+            }
+        }
+    }
+    for y in 0..height {
+        for x in 0..width {
+            for c in 0..channels {
+                let value : f64 = kernel.iter()
+                    .enumerate()
+                    .map(|(k, weight)| {
+                        let sy = clamp(y as isize + k as isize - radius,
+                                       height);
+                        weight * rows[(sy * width + x) * channels + c]
+                    })
+                    .sum();
+                pixels[y * stride + x * channels + c] = value.round() as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_spreads_pixels_evenly() {
+        // 9x9 gray pixels with a row stride of 10
+        let mut pixels = vec![0u8; 90];
+        pixels[4 * 10 + 4] = 255;
+        gaussian(&mut pixels, 9, 9, 10, 1, 1.);
+
+        let at = |x: usize, y: usize| pixels[y * 10 + x];
+        assert!(at(4, 4) < 255 && at(4, 4) > at(4, 3));
+        assert_eq!(at(4, 3), at(3, 4));
+        assert_eq!(at(4, 3), at(4, 5));
+        assert_eq!(at(3, 3), at(5, 5));
+        assert_eq!(0, at(0, 0));
+        // The padding at the end of each row is left alone
+        assert_eq!(0, pixels[4 * 10 + 9]);
+
+        let mut flat = vec![100u8; 4 * 4 * 3];
+        gaussian(&mut flat, 4, 4, 12, 3, 2.);
+        assert!(flat.iter().all(|&v| v == 100));
+    }
+}
@@ -0,0 +1,72 @@
+/// A transition from 0 to 1, advanced by the frame clock
+pub struct Fade {
+    /// In microseconds, like the frame time
+    duration: i64,
+    /// The frame time of the first frame, once there was one
+    start: Option<i64>,
+    progress: f64,
+}
+
+impl Fade {
+    /// A fade that has already finished
+    pub fn new(duration: i64) -> Self {
+        Fade {
+            duration,
+            start: None,
+            progress: 1.,
+        }
+    }
+
+    /// Start over from 0 with the next frame
+    pub fn restart(&mut self) {
+        self.start = None;
+        self.progress = 0.;
+    }
+
+    pub fn finish(&mut self) {
+        self.progress = 1.;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.progress < 1.
+    }
+
+    /// Move to the frame at `now`. Returns whether the fade is still running.
+    pub fn tick(&mut self, now: i64) -> bool {
+        if !self.is_running() {
+            return false;
+        }
+        let start = *self.start.get_or_insert(now);
+        self.progress = ((now - start) as f64 / self.duration as f64).min(1.);
+        self.is_running()
+    }
+
+    /// How far along the fade is, eased in and out
+    pub fn progress(&self) -> f64 {
+        let t = self.progress;
+        t * t * (3. - 2. * t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_fades_from_the_first_frame() {
+        let mut fade = Fade::new(1000);
+        assert!(!fade.is_running());
+        assert_eq!(1., fade.progress());
+
+        fade.restart();
+        assert!(fade.tick(5000));
+        assert_eq!(0., fade.progress());
+        assert!(fade.tick(5500));
+        assert_eq!(0.5, fade.progress());
+        assert!(fade.tick(5250));
+        assert!(fade.progress() < 0.5);
+        assert!(!fade.tick(6200));
+        assert_eq!(1., fade.progress());
+        assert!(!fade.tick(7000));
+    }
+}
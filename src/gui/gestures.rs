@@ -0,0 +1,175 @@
+use super::GUI_INST;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use gtk;
+use gtk::prelude::*;
+use gdk;
+
+/// What a gesture on the cover asks for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    PlayPause,
+    Next,
+    Previous,
+    /// Show the details of the track, pointing at `(x, y)` on the cover
+    Details(f64, f64),
+    /// Change the volume by this many steps
+    Volume(i32),
+}
+
+/// Touch and mouse gestures on the cover
+pub struct Gestures {
+    details: gtk::Popover,
+    details_label: gtk::Label,
+    // GTK 3 widgets don't keep their gestures alive
+    _swipe: gtk::GestureSwipe,
+    _drag: gtk::GestureDrag,
+    _tap: gtk::GestureMultiPress,
+    _long_press: gtk::GestureLongPress,
+}
+
+impl Gestures {
+    /// How fast a swipe has to be to skip, in pixels per second
+    const SWIPE_VELOCITY : f64 = 500.;
+    /// How far to drag for each volume step, in pixels
+    const VOLUME_DISTANCE : f64 = 32.;
+
+    pub fn new(cover: &gtk::DrawingArea) -> Self {
+        cover.add_events((gdk::EventMask::BUTTON_PRESS_MASK
+                          | gdk::EventMask::BUTTON_RELEASE_MASK
+                          | gdk::EventMask::BUTTON_MOTION_MASK
+                          | gdk::EventMask::TOUCH_MASK).bits() as i32);
+
+        // The volume steps taken so far in the current drag. A drag that
+        // changed the volume doesn't skip when it ends in a flick.
+        let steps = Rc::new(Cell::new(0));
+        let adjusted = Rc::new(Cell::new(false));
+
+        let swipe = gtk::GestureSwipe::new(cover);
+        {
+            let adjusted = adjusted.clone();
+            swipe.connect_swipe(move |_, vx, vy| {
+                if adjusted.get() {
+                    return;
+                }
+                if let Some(action) = swipe_action(vx, vy) {
+                    run(action);
+                }
+            });
+        }
+
+        let drag = gtk::GestureDrag::new(cover);
+        {
+            let steps = steps.clone();
+            let adjusted = adjusted.clone();
+            drag.connect_drag_begin(move |_, _, _| {
+                steps.set(0);
+                adjusted.set(false);
+            });
+        }
+        drag.connect_drag_update(move |_, dx, dy| {
+            if dy.abs() <= dx.abs() {
+                return;
+            }
+            let total = volume_steps(dy);
+            if total != steps.get() {
+                run(Action::Volume(total - steps.get()));
+                steps.set(total);
+                adjusted.set(true);
+            }
+        });
+
+        // A tap ending a long press only closes the details
+        let long_pressed = Rc::new(Cell::new(false));
+        let tap = gtk::GestureMultiPress::new(cover);
+        {
+            let long_pressed = long_pressed.clone();
+            tap.connect_pressed(move |_, _, _, _| long_pressed.set(false));
+        }
+        {
+            let long_pressed = long_pressed.clone();
+            tap.connect_released(move |_, n_press, _, _| {
+                if n_press == 1 && !long_pressed.get() {
+                    run(Action::PlayPause);
+                }
+            });
+        }
+        let long_press = gtk::GestureLongPress::new(cover);
+        long_press.connect_pressed(move |_, x, y| {
+            long_pressed.set(true);
+            run(Action::Details(x, y));
+        });
+
+        let details = gtk::Popover::new(Some(cover));
+        let details_label = gtk::Label::new(None);
+        details_label.set_line_wrap(true);
+        details_label.set_max_width_chars(40);
+        details_label.set_margin_top(6);
+        details_label.set_margin_bottom(6);
+        details_label.set_margin_start(6);
+        details_label.set_margin_end(6);
+        details_label.show();
+        details.add(&details_label);
+
+        Gestures {
+            details,
+            details_label,
+            _swipe: swipe,
+            _drag: drag,
+            _tap: tap,
+            _long_press: long_press,
+        }
+    }
+
+    /// Show `markup` in a popover pointing at `(x, y)` on the cover
+    pub fn show_details(&self, markup: &str, x: f64, y: f64) {
+        self.details_label.set_markup(markup);
+        self.details.set_pointing_to(&gtk::Rectangle {
+            x: x as i32, y: y as i32, width: 1, height: 1,
+        });
+        self.details.show();
+    }
+}
+
+fn run(action: Action) {
+    if let Some(gui) = unsafe { GUI_INST.as_mut() } {
+        gui.gesture(action);
+    }
+}
+
+/// The action for a swipe at `(vx, vy)` pixels per second, if it's a fast,
+/// mostly horizontal one
+fn swipe_action(vx: f64, vy: f64) -> Option<Action> {
+    if vx.abs() < Gestures::SWIPE_VELOCITY || vx.abs() < vy.abs() {
+        None
+    } else if vx < 0. {
+        Some(Action::Next)
+    } else {
+        Some(Action::Previous)
+    }
+}
+
+/// The volume steps for dragging `dy` pixels down, negative to lower it
+fn volume_steps(dy: f64) -> i32 {
+    (-dy / Gestures::VOLUME_DISTANCE) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_classifies_swipes_and_drags() {
+        assert_eq!(Some(Action::Next), swipe_action(-900., 100.));
+        assert_eq!(Some(Action::Previous), swipe_action(900., -100.));
+        // Too slow, or mostly vertical
+        assert_eq!(None, swipe_action(200., 0.));
+        assert_eq!(None, swipe_action(900., 1200.));
+
+        assert_eq!(0, volume_steps(-20.));
+        assert_eq!(2, volume_steps(-70.));
+        assert_eq!(-1, volume_steps(40.));
+    }
+}
@@ -0,0 +1,132 @@
+use config;
+
+use gtk;
+use gdk;
+
+/// What a key can be bound to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    PlayPause,
+    SeekForward,
+    SeekBackward,
+    Next,
+    Previous,
+    Fullscreen,
+    VolumeUp,
+    VolumeDown,
+    NextPlayer,
+    Quit,
+}
+
+/// The key bindings from the config, parsed
+pub struct Keys {
+    bindings: Vec<(u32, gdk::ModifierType, Action)>,
+}
+
+impl Keys {
+    pub fn new(config: &config::Keys) -> Self {
+        let keys = [
+            (&config.play_pause, Action::PlayPause),
+            (&config.seek_forward, Action::SeekForward),
+            (&config.seek_backward, Action::SeekBackward),
+            (&config.next, Action::Next),
+            (&config.previous, Action::Previous),
+            (&config.fullscreen, Action::Fullscreen),
+            (&config.volume_up, Action::VolumeUp),
+            (&config.volume_down, Action::VolumeDown),
+            (&config.next_player, Action::NextPlayer),
+            (&config.quit, Action::Quit),
+        ];
+        let bindings = keys.iter()
+            .filter(|&&(accelerator, _)| !accelerator.is_empty())
+            .filter_map(|&(accelerator, action)| {
+                match gtk::accelerator_parse(accelerator) {
+                    (0, _) => {
+                        eprintln!("Invalid key binding {:?} for {:?}",
+                                  accelerator, action);
+                        None
+                    },
+                    (key, modifiers) => Some((key, modifiers, action)),
+                }
+            })
+            .collect();
+        Keys { bindings }
+    }
+
+    /// The action for pressing `key` while holding `modifiers`
+    pub fn action(&self, key: u32, modifiers: gdk::ModifierType)
+                  -> Option<Action> {
+        let key = gdk::keyval_to_lower(key);
+        let modifiers = modifiers & (gdk::ModifierType::SHIFT_MASK
+                                     | gdk::ModifierType::CONTROL_MASK
+                                     | gdk::ModifierType::MOD1_MASK
+                                     | gdk::ModifierType::SUPER_MASK);
+        // Symbols like `plus` need shift on some layouts, so fall back to
+        // ignoring it for keys that have no upper case
+        let symbol = key == gdk::keyval_to_upper(key);
+        Self::find(&self.bindings, key, modifiers).or_else(|| {
+            if symbol {
+                Self::find(&self.bindings, key,
+                           modifiers - gdk::ModifierType::SHIFT_MASK)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn find(bindings: &[(u32, gdk::ModifierType, Action)], key: u32,
+            modifiers: gdk::ModifierType) -> Option<Action> {
+        bindings.iter()
+                .find(|&&(k, m, _)| k == key && m == modifiers)
+                .map(|&(_, _, action)| action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gdk::enums::key;
+
+    #[test]
+    fn it_prefers_exact_modifiers() {
+        let shift = gdk::ModifierType::SHIFT_MASK;
+        let bindings = vec![
+            (key::Right, gdk::ModifierType::empty(), Action::SeekForward),
+            (key::Right, shift, Action::Next),
+        ];
+        assert_eq!(Some(Action::Next),
+                   Keys::find(&bindings, key::Right, shift));
+        assert_eq!(Some(Action::SeekForward),
+                   Keys::find(&bindings, key::Right,
+                              gdk::ModifierType::empty()));
+        assert_eq!(None, Keys::find(&bindings, key::Left, shift));
+    }
+
+    #[test]
+    fn it_binds_the_keys_from_the_config() {
+        // Parsing accelerators doesn't need a display, which `gtk::init`
+        // would. This is the only test using GTK, so it can claim the thread.
+        unsafe { gtk::set_initialized(); }
+        let keys = Keys::new(&config::Keys {
+            quit: "<Control>nope".to_string(),
+            ..config::Keys::default()
+        });
+        let none = gdk::ModifierType::empty();
+        let shift = gdk::ModifierType::SHIFT_MASK;
+        let control = gdk::ModifierType::CONTROL_MASK;
+
+        // `plus` needs shift on many layouts
+        assert_eq!(Some(Action::VolumeUp), keys.action(key::plus, none));
+        assert_eq!(Some(Action::VolumeUp), keys.action(key::plus, shift));
+        // But letters don't fall back
+        assert_eq!(Some(Action::NextPlayer), keys.action(key::P, none));
+        assert_eq!(None, keys.action(key::P, shift));
+        assert_eq!(Some(Action::Next), keys.action(key::Right, shift));
+
+        // The invalid binding is dropped instead of binding a key
+        assert_eq!(9, keys.bindings.len());
+        assert!(keys.bindings.iter().all(|&(_, _, a)| a != Action::Quit));
+        assert_eq!(None, keys.action(key::q, none));
+        assert_eq!(None, keys.action(key::q, control));
+    }
+}
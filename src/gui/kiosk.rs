@@ -0,0 +1,140 @@
+use config;
+
+use std::mem;
+use std::time::{Duration, Instant};
+
+use gtk;
+use gtk::prelude::*;
+use gdk;
+use gdk::prelude::*;
+
+/// Fullscreen mode for wall displays: no decorations, no cursor, no
+/// screensaver
+pub struct Kiosk {
+    config: config::Kiosk,
+    last_motion: Instant,
+    cursor_hidden: bool,
+    /// From `gtk::Application::inhibit` while we keep the screen on
+    inhibit_cookie: Option<u32>,
+}
+
+impl Kiosk {
+    /// How often we check whether to hide the cursor, in seconds
+    pub const CHECK_INTERVAL : u32 = 1;
+
+    pub fn new() -> Self {
+        Kiosk {
+            config: config::Kiosk::default(),
+            last_motion: Instant::now(),
+            cursor_hidden: false,
+            inhibit_cookie: None,
+        }
+    }
+
+    /// Switch to the settings in `config`
+    pub fn apply(&mut self, window: &gtk::ApplicationWindow,
+                 config: &config::Kiosk, playing: bool) {
+        let old = mem::replace(&mut self.config, config.clone());
+        if config.enabled && (!old.enabled || config.monitor != old.monitor) {
+            window.set_decorated(false);
+            if let Some(ref monitor) = config.monitor {
+                Self::move_to_monitor(window, monitor);
+            }
+            window.fullscreen();
+        } else if !config.enabled && old.enabled {
+            window.set_decorated(true);
+            window.unfullscreen();
+        }
+
+        if !config.enabled {
+            self.show_cursor(window);
+        }
+        self.set_playing(window, playing);
+    }
+
+    fn move_to_monitor(window: &gtk::ApplicationWindow,
+                       monitor: &config::Monitor) {
+        let screen = match window.get_screen() {
+            Some(screen) => screen,
+            None => return,
+        };
+        let count = screen.get_n_monitors();
+        let index = match *monitor {
+            config::Monitor::Index(i) if i >= 0 && i < count => Some(i),
+            config::Monitor::Index(_) => None,
+            config::Monitor::Name(ref name) => (0..count).find(|&i| {
+                screen.get_monitor_plug_name(i).as_ref() == Some(name)
+            }),
+        };
+        match index {
+            // `fullscreen_on_monitor` needs GTK 3.18, but window managers
+            // fullscreen windows on the monitor they're on
+            Some(i) => {
+                let geometry = screen.get_monitor_geometry(i);
+                window.move_(geometry.x, geometry.y);
+            },
+            None => eprintln!("Couldn't find the monitor {:?}", monitor),
+        }
+    }
+
+    /// The mouse moved
+    pub fn moved(&mut self, window: &gtk::ApplicationWindow) {
+        self.last_motion = Instant::now();
+        self.show_cursor(window);
+    }
+
+    /// Hide the cursor if the mouse hasn't moved for a while
+    pub fn check_idle(&mut self, window: &gtk::ApplicationWindow) {
+        let timeout = Duration::from_secs(self.config.hide_cursor as u64);
+        if !self.config.enabled || self.cursor_hidden
+                || self.last_motion.elapsed() < timeout {
+            return;
+        }
+        if let Some(gdk_window) = window.get_window() {
+            let cursor = gdk::Cursor::new_for_display(
+                &gdk_window.get_display(), gdk::CursorType::BlankCursor
+            );
+            gdk_window.set_cursor(&cursor);
+            self.cursor_hidden = true;
+        }
+    }
+
+    fn show_cursor(&mut self, window: &gtk::ApplicationWindow) {
+        if !self.cursor_hidden {
+            return;
+        }
+        if let Some(gdk_window) = window.get_window() {
+            gdk_window.set_cursor(None);
+        }
+        self.cursor_hidden = false;
+    }
+
+    /// Keep the screen on while playing
+    pub fn set_playing(&mut self, window: &gtk::ApplicationWindow,
+                       playing: bool) {
+        let app = match window.get_application() {
+            Some(app) => app,
+            None => return,
+        };
+        let inhibit = self.config.enabled && self.config.inhibit_idle
+                      && playing;
+        match (inhibit, self.inhibit_cookie) {
+            (true, None) => {
+                let cookie = app.inhibit(
+                    window, gtk::ApplicationInhibitFlags::IDLE, "Playing music"
+                );
+                // 0 means the session doesn't support inhibiting
+                if cookie == 0 {
+                    eprintln!("Couldn't keep the screen from blanking");
+                } else {
+                    self.inhibit_cookie = Some(cookie);
+                }
+            },
+            (false, Some(cookie)) => {
+                app.uninhibit(cookie);
+                self.inhibit_cookie = None;
+            },
+            _ => {},
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use config::Layout;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use gtk;
+use gtk::prelude::*;
+use gdk;
+use gdk::prelude::*;
+use glib;
+use serde_json;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Geometry {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+/// Arranges the widgets from `gui.glade` for the current `Layout`, and
+/// remembers where the window was for each layout
+pub struct Layouts {
+    window: gtk::ApplicationWindow,
+    wrapper: gtk::Overlay,
+    cover: gtk::DrawingArea,
+    info: gtk::Box,
+    bottom: gtk::Box,
+    /// The player button and its quit button
+    player: gtk::Box,
+    lists: gtk::Expander,
+    album: gtk::Label,
+    playback_status: gtk::Label,
+    /// Holds the cover next to the labels in the `Side` and `Compact` layouts
+    row: gtk::Box,
+    column: gtk::Box,
+    current: Layout,
+    geometry: HashMap<Layout, Geometry>,
+}
+
+impl Layouts {
+    const THUMBNAIL_SIZE : i32 = 64;
+
+    /// Layouts for the widgets in `builder`, which are arranged for
+    /// `Layout::Overlay`
+    pub fn new(builder: &gtk::Builder, window: &gtk::ApplicationWindow)
+               -> Self {
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        let column = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        row.show();
+        column.show();
+
+        window.get_style_context().unwrap()
+              .add_class(&Self::class(Layout::Overlay));
+
+        Layouts {
+            window: window.clone(),
+            wrapper: builder.get_object("wrapper").unwrap(),
+            cover: builder.get_object("cover").unwrap(),
+            info: builder.get_object("info").unwrap(),
+            bottom: builder.get_object("bottom").unwrap(),
+            player: builder.get_object("player_bar").unwrap(),
+            lists: builder.get_object("lists").unwrap(),
+            album: builder.get_object("album").unwrap(),
+            playback_status: builder.get_object("playback_status").unwrap(),
+            row,
+            column,
+            current: Layout::Overlay,
+            geometry: Self::load(),
+        }
+    }
+
+    fn path() -> Option<PathBuf> {
+        glib::get_user_cache_dir()
+            .map(|dir| dir.join("mprvis").join("layouts.json"))
+    }
+
+    fn load() -> HashMap<Layout, Geometry> {
+        Self::path()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default()
+    }
+
+    /// Remember where the window is and write it to disk
+    pub fn save(&mut self) {
+        self.remember();
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return,
+        };
+        let json = serde_json::to_vec(&self.geometry).unwrap();
+        if let Err(err) = path.parent().map_or(Ok(()), fs::create_dir_all)
+                              .and_then(|_| fs::write(&path, json)) {
+            eprintln!("Couldn't save the window geometry to {:?}: {}", path,
+                      err);
+        }
+    }
+
+    fn remember(&mut self) {
+        // Fullscreen isn't where the user put the window
+        let fullscreen = self.window.get_window().map_or(false, |w| {
+            w.get_state().contains(gdk::WindowState::FULLSCREEN)
+        });
+        if fullscreen || !self.window.is_visible() {
+            return;
+        }
+        let (x, y) = self.window.get_position();
+        let (width, height) = self.window.get_size();
+        self.geometry.insert(self.current, Geometry { x, y, width, height });
+    }
+
+    /// Move and size the window like it was the last time `current` was
+    /// used
+    pub fn restore(&self) {
+        match self.geometry.get(&self.current) {
+            Some(geometry) => {
+                self.window.move_(geometry.x, geometry.y);
+                self.window.resize(geometry.width, geometry.height);
+            },
+            None => {
+                let (width, height) = match self.current {
+                    Layout::Compact => (480, Self::THUMBNAIL_SIZE),
+                    Layout::Side => (900, 450),
+                    Layout::Overlay | Layout::Cover => (600, 600),
+                };
+                self.window.resize(width, height);
+            },
+        }
+    }
+
+    /// The layout after the current one
+    pub fn next(&self) -> Layout {
+        let i = Layout::ALL.iter().position(|&l| l == self.current).unwrap();
+        Layout::ALL[(i + 1) % Layout::ALL.len()]
+    }
+
+    /// Whether the labels are on top of the cover, so it has to be darkened
+    pub fn overlays_cover(&self) -> bool {
+        self.current == Layout::Overlay
+    }
+
+    /// Whether the labels only get one line each
+    pub fn compact(&self) -> bool {
+        self.current == Layout::Compact
+    }
+
+    /// Rearrange the window for `layout`
+    pub fn set(&mut self, layout: Layout) {
+        if layout == self.current {
+            return;
+        }
+
+        for container in &[self.wrapper.clone().upcast::<gtk::Container>(),
+                           self.row.clone().upcast(),
+                           self.column.clone().upcast()] {
+            for child in container.get_children() {
+                container.remove(&child);
+            }
+        }
+
+        let compact = layout == Layout::Compact;
+        match layout {
+            Layout::Overlay => {
+                self.wrapper.add(&self.cover);
+                self.wrapper.add_overlay(&self.info);
+                self.wrapper.add_overlay(&self.bottom);
+                self.wrapper.add_overlay(&self.player);
+                self.wrapper.add_overlay(&self.lists);
+            },
+            Layout::Cover => {
+                self.wrapper.add(&self.cover);
+            },
+            Layout::Side => {
+                self.row.set_homogeneous(true);
+                self.row.pack_start(&self.cover, true, true, 0);
+                self.column.pack_start(&self.info, true, true, 0);
+                self.column.pack_end(&self.bottom, false, false, 0);
+                self.row.pack_start(&self.column, true, true, 0);
+                self.wrapper.add(&self.row);
+                self.wrapper.add_overlay(&self.player);
+                self.wrapper.add_overlay(&self.lists);
+            },
+            Layout::Compact => {
+                self.row.set_homogeneous(false);
+                self.row.pack_start(&self.cover, false, false, 0);
+                self.column.pack_start(&self.info, true, true, 0);
+                self.row.pack_start(&self.column, true, true, 0);
+                self.row.pack_end(&self.bottom, false, false, 0);
+                self.wrapper.add(&self.row);
+            },
+        }
+
+        let thumbnail = if compact { Self::THUMBNAIL_SIZE } else { -1 };
+        self.cover.set_size_request(thumbnail, thumbnail);
+        self.album.set_visible(!compact);
+        self.playback_status.set_visible(!compact);
+
+        let context = self.window.get_style_context().unwrap();
+        context.remove_class(&Self::class(self.current));
+        context.add_class(&Self::class(layout));
+
+        self.current = layout;
+    }
+
+    /// The style class on the window, so stylesheets can adapt
+    fn class(layout: Layout) -> String {
+        let name = match layout {
+            Layout::Overlay => "overlay",
+            Layout::Cover => "cover",
+            Layout::Compact => "compact",
+            Layout::Side => "side",
+        };
+        format!("layout-{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_stores_geometry_by_layout_name() {
+        let mut geometry = HashMap::new();
+        geometry.insert(Layout::Compact, Geometry {
+            x: 0, y: 1016, width: 480, height: 64,
+        });
+        let json = serde_json::to_string(&geometry).unwrap();
+        assert_eq!("{\"compact\":{\"x\":0,\"y\":1016,\"width\":480,\
+                    \"height\":64}}", json);
+        assert_eq!(geometry, serde_json::from_str(&json).unwrap());
+    }
+}
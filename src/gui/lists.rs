@@ -0,0 +1,158 @@
+use mpris;
+use super::GUI_INST;
+
+use std::collections::HashMap;
+
+use gtk;
+use gtk::prelude::*;
+use gdk_pixbuf;
+use gdk_pixbuf::prelude::*;
+
+/// The collapsible panel with the "up next" queue and the playlists
+pub struct Lists {
+    expander: gtk::Expander,
+    up_next_header: gtk::Label,
+    up_next_list: gtk::ListBox,
+    playlists_header: gtk::Label,
+    playlists_list: gtk::ListBox,
+    up_next: Vec<mpris::Track>,
+    up_next_images: Vec<gtk::Image>,
+    thumbnails: HashMap<String, gdk_pixbuf::Pixbuf>,
+    playlists: Vec<mpris::Playlist>,
+}
+
+impl Lists {
+    const THUMBNAIL_SIZE : i32 = 48;
+
+    pub fn new(builder: &gtk::Builder) -> Self {
+        let up_next_list: gtk::ListBox = builder.get_object("up_next").unwrap();
+        up_next_list.connect_row_activated(|_, row| {
+            let gui = unsafe { GUI_INST.as_ref().unwrap() };
+            if let Some(track) = gui.lists.up_next.get(row.get_index() as usize) {
+                gui.send_command(mpris::Command::GoTo(track.id.clone()));
+            }
+        });
+        let playlists_list: gtk::ListBox = builder.get_object("playlists")
+                                                  .unwrap();
+        playlists_list.connect_row_activated(|_, row| {
+            let gui = unsafe { GUI_INST.as_ref().unwrap() };
+            let index = row.get_index() as usize;
+            if let Some(playlist) = gui.lists.playlists.get(index) {
+                gui.send_command(
+                    mpris::Command::ActivatePlaylist(playlist.id.clone())
+                );
+            }
+        });
+
+        Lists {
+            expander: builder.get_object("lists").unwrap(),
+            up_next_header: builder.get_object("up_next_header").unwrap(),
+            up_next_list,
+            playlists_header: builder.get_object("playlists_header").unwrap(),
+            playlists_list,
+            up_next: Vec::new(),
+            up_next_images: Vec::new(),
+            thumbnails: HashMap::new(),
+            playlists: Vec::new(),
+        }
+    }
+
+    pub fn update_up_next(&mut self, tracks: Vec<mpris::Track>) {
+        Self::clear(&self.up_next_list);
+        self.thumbnails.retain(|id, _| tracks.iter().any(|t| &t.id == id));
+
+        self.up_next_images = tracks.iter().map(|track| {
+            let image = gtk::Image::new();
+            image.set_size_request(Self::THUMBNAIL_SIZE, Self::THUMBNAIL_SIZE);
+            if let Some(thumbnail) = self.thumbnails.get(&track.id) {
+                image.set_from_pixbuf(thumbnail);
+            }
+
+            let mut text = track.data.title.clone()
+                                .unwrap_or("Unknown title".to_string());
+            if let Some(ref artist) = track.data.artist {
+                text.push_str("\n");
+                text.push_str(artist);
+            }
+            let label = gtk::Label::new(text.as_str());
+            label.set_halign(gtk::Align::Start);
+            label.set_line_wrap(true);
+
+            let row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+            row.add(&image);
+            row.add(&label);
+            self.up_next_list.add(&row);
+            image
+        }).collect();
+        self.up_next = tracks;
+
+        self.up_next_list.show_all();
+        self.update_visibility();
+    }
+
+    pub fn update_thumbnail(&mut self, id: String, data: &[u8]) {
+        let loader = gdk_pixbuf::PixbufLoader::new();
+        let thumbnail = loader.write(data)
+                              .and_then(|_| loader.close())
+                              .ok()
+                              .and_then(|_| loader.get_pixbuf())
+                              .and_then(|img| img.scale_simple(
+                                  Self::THUMBNAIL_SIZE, Self::THUMBNAIL_SIZE,
+                                  gdk_pixbuf::InterpType::Bilinear
+                              ));
+        let thumbnail = match thumbnail {
+            Some(thumbnail) => thumbnail,
+            None => {
+                eprintln!("Couldn't parse thumbnail for {}", id);
+                return;
+            },
+        };
+
+        if let Some(i) = self.up_next.iter().position(|t| t.id == id) {
+            self.up_next_images[i].set_from_pixbuf(&thumbnail);
+        }
+        // Keep it even if the track isn't listed yet, the thumbnail may have
+        // overtaken the `UpNext` event
+        self.thumbnails.insert(id, thumbnail);
+    }
+
+    pub fn update_playlists(&mut self, playlists: Vec<mpris::Playlist>) {
+        Self::clear(&self.playlists_list);
+        for playlist in &playlists {
+            let label = gtk::Label::new(playlist.name.as_str());
+            label.set_halign(gtk::Align::Start);
+            self.playlists_list.add(&label);
+        }
+        self.playlists = playlists;
+
+        self.playlists_list.show_all();
+        self.update_visibility();
+    }
+
+    pub fn update_active_playlist(&self, active: Option<mpris::Playlist>) {
+        let row = active.and_then(|active| {
+            self.playlists.iter().position(|p| p.id == active.id)
+        }).and_then(|i| self.playlists_list.get_row_at_index(i as i32));
+        match row {
+            Some(ref row) => self.playlists_list.select_row(row),
+            None => self.playlists_list.select_row(None),
+        }
+    }
+
+    fn clear(list: &gtk::ListBox) {
+        for child in list.get_children() {
+            list.remove(&child);
+        }
+    }
+
+    /// Only show the parts of the panel the player has data for
+    fn update_visibility(&self) {
+        let has_up_next = !self.up_next.is_empty();
+        let has_playlists = !self.playlists.is_empty();
+        self.up_next_header.set_visible(has_up_next);
+        self.up_next_list.set_visible(has_up_next);
+        self.playlists_header.set_visible(has_playlists);
+        self.playlists_list.set_visible(has_playlists);
+        self.expander.set_visible(has_up_next || has_playlists);
+    }
+}
@@ -0,0 +1,329 @@
+use config;
+use lyrics::{self, Lyrics};
+use mpris;
+use super::GUI_INST;
+use super::fade::Fade;
+
+use gtk;
+use gtk::prelude::*;
+use gdk;
+use gdk::prelude::*;
+use glib;
+use cairo;
+use pango;
+use pango::LayoutExt;
+
+/// The lyrics of the current track below the labels, scrolled along with
+/// the playback position
+pub struct View {
+    area: gtk::DrawingArea,
+    config: config::Lyrics,
+    lyrics: Option<Lyrics>,
+    /// The track the lyrics were looked up for
+    track: Option<mpris::Metadata>,
+    /// The last reported position, and the monotonic time it was reported at
+    position: (i64, i64),
+    playing: bool,
+    /// The line that's sung, and the one we're scrolling away from
+    current: Option<usize>,
+    previous: Option<usize>,
+    scroll: Fade,
+    /// Wakes us up when the next line starts
+    timer: Option<glib::SourceId>,
+    /// The clock we asked for updates while scrolling
+    clock: Option<gdk::FrameClock>,
+    /// Each line laid out for `width`
+    layouts: Vec<pango::Layout>,
+    width: i32,
+    /// How much taller than the view unsynced lyrics are, in pixels
+    overflow: f64,
+    /// Whether the window is the thin strip without room for lyrics
+    compact: bool,
+}
+
+impl View {
+    /// In microseconds
+    const SCROLL : i64 = 300_000;
+    /// How opaque the lines other than the current one are
+    const DIM : f64 = 0.4;
+    /// Between the lines, in pixels
+    const SPACING : i32 = 6;
+    /// The shortest time between redraws of scrolling unsynced lyrics, in
+    /// milliseconds
+    const MIN_INTERVAL : i64 = 40;
+
+    pub fn new(area: gtk::DrawingArea, config: &config::Lyrics) -> Self {
+        area.connect_draw(|_, context| {
+            match unsafe { GUI_INST.as_mut() } {
+                Some(gui) => gui.lyrics.draw(context),
+                None => Inhibit(false),
+            }
+        });
+        View {
+            area,
+            config: config.clone(),
+            lyrics: None,
+            track: None,
+            position: (0, glib::get_monotonic_time()),
+            playing: false,
+            current: None,
+            previous: None,
+            scroll: Fade::new(Self::SCROLL),
+            timer: None,
+            clock: None,
+            layouts: Vec::new(),
+            width: 0,
+            overflow: 0.,
+            compact: false,
+        }
+    }
+
+    /// Switch to the settings in `config`, looking the lyrics up again
+    pub fn configure(&mut self, config: &config::Lyrics) {
+        self.config = config.clone();
+        if let Some(data) = self.track.take() {
+            self.load(&data);
+        }
+    }
+
+    /// Look for the lyrics of `data` if it's another track
+    pub fn load(&mut self, data: &mpris::Metadata) {
+        let same = self.track.as_ref().is_some_and(|track| {
+            (&track.title, &track.artist, &track.url, &track.lyrics)
+                == (&data.title, &data.artist, &data.url, &data.lyrics)
+        });
+        if same {
+            return;
+        }
+        self.track = Some(data.clone());
+        self.lyrics = if self.config.enabled {
+            lyrics::find(&self.config, data)
+        } else {
+            None
+        };
+        self.layouts.clear();
+        self.current = None;
+        self.previous = None;
+        self.scroll.finish();
+        self.show();
+        self.update();
+    }
+
+    pub fn set_compact(&mut self, compact: bool) {
+        self.compact = compact;
+        self.show();
+    }
+
+    fn show(&self) {
+        self.area.set_visible(self.lyrics.is_some() && !self.compact);
+        self.area.queue_draw();
+    }
+
+    /// The player reported being at `position`, in microseconds
+    pub fn set_position(&mut self, position: i64) {
+        self.position = (position, glib::get_monotonic_time());
+        self.update();
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.position = (self.position(), glib::get_monotonic_time());
+        self.playing = playing;
+        self.update();
+    }
+
+    /// The current position, extrapolated from the last reported one
+    fn position(&self) -> i64 {
+        let (position, at) = self.position;
+        if self.playing {
+            position + glib::get_monotonic_time() - at
+        } else {
+            position
+        }
+    }
+
+    /// Scroll to the line at the current position, and wait for the next one
+    fn update(&mut self) {
+        if let Some(timer) = self.timer.take() {
+            glib::source_remove(timer);
+        }
+        let position = self.position();
+        let (synced, current, next) = match self.lyrics {
+            Some(ref lyrics) => (lyrics.synced, lyrics.current(position),
+                                 lyrics.next_time(position)),
+            None => return,
+        };
+        if !synced {
+            // Scrolled along in `draw`, which keeps waking us up
+            self.area.queue_draw();
+            return;
+        }
+        if current != self.current {
+            self.previous = self.current;
+            self.current = current;
+            self.scroll.restart();
+            self.animate();
+        }
+        if let Some(next) = next.filter(|_| self.playing) {
+            // Rounded up, so we don't wake up just before it
+            self.wake_in((next - position) / 1000 + 1);
+        }
+    }
+
+    /// Call `update` again in `delay` milliseconds
+    fn wake_in(&mut self, delay: i64) {
+        self.timer = Some(glib::timeout_add(delay as u32, || {
+            if let Some(gui) = unsafe { GUI_INST.as_mut() } {
+                gui.lyrics.timer = None;
+                gui.lyrics.update();
+            }
+            glib::Continue(false)
+        }));
+    }
+
+    /// How far unsynced lyrics `overflow` pixels taller than the view are
+    /// scrolled at `position` of a track `length` long, so the last line
+    /// comes into view as the track ends
+    fn unsynced_offset(position: i64, length: Option<i64>, overflow: f64)
+                       -> f64 {
+        match length {
+            Some(length) if length > 0 && overflow > 0. => {
+                let t = position as f64 / length as f64;
+                overflow * t.clamp(0., 1.)
+            },
+            _ => 0.,
+        }
+    }
+
+    fn animate(&mut self) {
+        if self.clock.is_none() {
+            self.clock = self.area.get_frame_clock();
+            match self.clock {
+                Some(ref clock) => clock.begin_updating(),
+                // Not shown, so there's nothing to animate
+                None => self.scroll.finish(),
+            }
+        }
+        self.area.queue_draw();
+    }
+
+    /// Scroll to where we are at the frame time `now`
+    pub fn tick(&mut self, now: i64) {
+        if self.clock.is_none() {
+            return;
+        }
+        if !self.scroll.tick(now) {
+            if let Some(clock) = self.clock.take() {
+                clock.end_updating();
+            }
+        }
+        self.area.queue_draw();
+    }
+
+    /// Lay the lines out for `width` pixels, unless they already are
+    fn lay_out(&mut self, width: i32) {
+        if width == self.width && !self.layouts.is_empty() {
+            return;
+        }
+        let lines = match self.lyrics {
+            Some(ref lyrics) => &lyrics.lines,
+            None => return,
+        };
+        self.width = width;
+        self.layouts = lines.iter().filter_map(|line| {
+            let layout = self.area.create_pango_layout(line.text.as_str())?;
+            layout.set_width(width * pango::SCALE);
+            layout.set_wrap(pango::WrapMode::WordChar);
+            layout.set_alignment(pango::Alignment::Center);
+            Some(layout)
+        }).collect();
+    }
+
+    fn draw(&mut self, context: &cairo::Context) -> Inhibit {
+        let synced = match self.lyrics {
+            Some(ref lyrics) => lyrics.synced,
+            None => return Inhibit(false),
+        };
+        let width = self.area.get_allocated_width();
+        let height = f64::from(self.area.get_allocated_height());
+        self.lay_out(width);
+
+        let mut tops = Vec::with_capacity(self.layouts.len());
+        let mut top = 0;
+        for layout in &self.layouts {
+            tops.push(top);
+            top += layout.get_pixel_size().1 + Self::SPACING;
+        }
+        let t = self.scroll.progress();
+        let shift = if synced {
+            // The point of the text that's in the middle of the view
+            let center = |i: usize| {
+                f64::from(tops[i])
+                    + f64::from(self.layouts[i].get_pixel_size().1) / 2.
+            };
+            let middle = |line: Option<usize>| match line {
+                Some(i) if i < tops.len() => center(i),
+                _ if !tops.is_empty() => center(0),
+                _ => height / 2.,
+            };
+            let from = middle(self.previous);
+            height / 2. - (from + (middle(self.current) - from) * t)
+        } else {
+            // Unsynced lyrics scroll from the top to the bottom over the
+            // whole track
+            let length = self.track.as_ref().and_then(|track| track.length);
+            self.overflow = (f64::from(top - Self::SPACING) - height).max(0.);
+            if self.playing && self.timer.is_none() && self.overflow > 0. {
+                if let Some(length) = length {
+                    // About when it moved by a pixel
+                    let interval = (length as f64 / self.overflow / 1000.)
+                        as i64;
+                    self.wake_in(interval.max(Self::MIN_INTERVAL));
+                }
+            }
+            -Self::unsynced_offset(self.position(), length, self.overflow)
+        };
+
+        let style = self.area.get_style_context().unwrap();
+        for (i, layout) in self.layouts.iter().enumerate() {
+            let top = f64::from(tops[i]) + shift;
+            let bottom = top + f64::from(layout.get_pixel_size().1);
+            if bottom < 0. || top > height {
+                continue;
+            }
+            let alpha = if !synced {
+                1.
+            } else if Some(i) == self.current {
+                Self::DIM + (1. - Self::DIM) * t
+            } else if Some(i) == self.previous {
+                1. - (1. - Self::DIM) * t
+            } else {
+                Self::DIM
+            };
+            context.push_group();
+            gtk::render_layout(&style, context, 0., top, layout);
+            context.pop_group_to_source();
+            context.paint_with_alpha(alpha);
+        }
+        Inhibit(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::View;
+
+    #[test]
+    fn it_scrolls_unsynced_lyrics_along() {
+        let length = Some(200_000_000);
+        assert_eq!(0., View::unsynced_offset(0, length, 300.));
+        assert_eq!(150., View::unsynced_offset(100_000_000, length, 300.));
+        assert_eq!(300., View::unsynced_offset(200_000_000, length, 300.));
+        // Past the end, e.g. while the length is still the last track's
+        assert_eq!(300., View::unsynced_offset(250_000_000, length, 300.));
+        // Fits into the view
+        assert_eq!(0., View::unsynced_offset(100_000_000, length, 0.));
+        // No idea where we are
+        assert_eq!(0., View::unsynced_offset(100_000_000, None, 300.));
+        assert_eq!(0., View::unsynced_offset(100_000_000, Some(0), 300.));
+    }
+}
@@ -0,0 +1,165 @@
+use mpris;
+use super::GUI_INST;
+
+use gtk;
+use gtk::prelude::*;
+use gdk;
+use gdk::prelude::*;
+use glib;
+
+/// A small always-on-top window with the cover, title and play/pause
+pub struct Mini {
+    pub window: gtk::Window,
+    pub cover: gtk::DrawingArea,
+    pub title: gtk::Label,
+    pub play_pause: gtk::Button,
+    pub play_pause_icon: gtk::Image,
+    /// How opaque the window is while the pointer is elsewhere
+    pub idle_opacity: f64,
+    /// Counts moves, so only the last one of a drag snaps
+    moves: u64,
+}
+
+impl Mini {
+    /// How close to a corner, in pixels, the window has to be dropped to
+    /// snap into it
+    const SNAP_DISTANCE : i32 = 64;
+    /// How long after the last move a drag is considered done, in ms
+    const SNAP_DELAY : u32 = 300;
+
+    pub fn new(builder: &gtk::Builder, app: &gtk::Application) -> Self {
+        let window : gtk::Window = builder.get_object("mini").unwrap();
+        window.set_application(app);
+        window.set_keep_above(true);
+        window.add_events((gdk::EventMask::BUTTON_PRESS_MASK
+                           | gdk::EventMask::ENTER_NOTIFY_MASK
+                           | gdk::EventMask::LEAVE_NOTIFY_MASK).bits() as i32);
+
+        // Move by dragging anywhere
+        window.connect_button_press_event(|window, event| {
+            if event.get_button() == 1 {
+                let (x, y) = window.get_position();
+                let (ex, ey) = event.get_position();
+                window.begin_move_drag(1, x + ex as i32, y + ey as i32,
+                                       event.get_time());
+            }
+            Inhibit(false)
+        });
+        window.connect_configure_event(|_, _| {
+            let gui = unsafe { GUI_INST.as_mut().unwrap() };
+            gui.mini.moved();
+            false
+        });
+        window.connect_enter_notify_event(|window, _| {
+            window.set_opacity(1.);
+            Inhibit(false)
+        });
+        window.connect_leave_notify_event(|window, event| {
+            // Moving onto the button isn't leaving
+            if event.get_detail() != gdk::NotifyType::Inferior {
+                let gui = unsafe { GUI_INST.as_ref().unwrap() };
+                window.set_opacity(gui.mini.idle_opacity);
+            }
+            Inhibit(false)
+        });
+        window.connect_delete_event(|_, _| {
+            let gui = unsafe { GUI_INST.as_mut().unwrap() };
+            gui.set_mini(false);
+            Inhibit(true)
+        });
+
+        let play_pause : gtk::Button = builder.get_object("mini_play_pause")
+                                              .unwrap();
+        play_pause.connect_clicked(|_| {
+            let gui = unsafe { GUI_INST.as_ref().unwrap() };
+            gui.send_command(mpris::Command::PlayPause);
+        });
+
+        Mini {
+            window,
+            cover: builder.get_object("mini_cover").unwrap(),
+            title: builder.get_object("mini_title").unwrap(),
+            play_pause,
+            play_pause_icon: builder.get_object("mini_play_pause_icon")
+                                    .unwrap(),
+            idle_opacity: 1.,
+            moves: 0,
+        }
+    }
+
+    pub fn show(&self) {
+        self.window.set_opacity(self.idle_opacity);
+        self.window.show();
+    }
+
+    /// The window moved or was resized. Snap it into a corner once it stays
+    /// put.
+    fn moved(&mut self) {
+        self.moves += 1;
+        let moves = self.moves;
+        glib::timeout_add(Self::SNAP_DELAY, move || {
+            if let Some(gui) = unsafe { GUI_INST.as_ref() } {
+                if gui.mini.moves == moves {
+                    gui.mini.snap();
+                }
+            }
+            glib::Continue(false)
+        });
+    }
+
+    fn snap(&self) {
+        let (gdk_window, screen) = match (self.window.get_window(),
+                                          self.window.get_screen()) {
+            (Some(w), Some(s)) => (w, s),
+            _ => return,
+        };
+        let area = screen.get_monitor_workarea(
+            screen.get_monitor_at_window(&gdk_window)
+        );
+        let (x, y) = self.window.get_position();
+        let (width, height) = self.window.get_size();
+        let area = (area.x, area.y, area.width, area.height);
+        if let Some((x, y)) = snap((x, y, width, height), area,
+                                   Self::SNAP_DISTANCE) {
+            self.window.move_(x, y);
+        }
+    }
+}
+
+/// Where a window at `(x, y, width, height)` snaps to in `area`, if it's
+/// within `distance` of a corner
+fn snap(window: (i32, i32, i32, i32), area: (i32, i32, i32, i32),
+        distance: i32) -> Option<(i32, i32)> {
+    let (x, y, width, height) = window;
+    let (left, top) = (area.0, area.1);
+    let (right, bottom) = (area.0 + area.2 - width, area.1 + area.3 - height);
+    let near = |pos: i32, edge: i32| (pos - edge).abs() <= distance;
+
+    let x = if near(x, left) { left }
+            else if near(x, right) { right }
+            else { return None };
+    let y = if near(y, top) { top }
+            else if near(y, bottom) { bottom }
+            else { return None };
+    if (x, y) == (window.0, window.1) {
+        None
+    } else {
+        Some((x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_snaps_to_nearby_corners() {
+        let area = (0, 24, 1920, 1056);
+        assert_eq!(Some((0, 24)), snap((30, 50, 300, 60), area, 64));
+        assert_eq!(Some((1620, 1020)), snap((1600, 1000, 300, 60), area, 64));
+        // Near an edge, but not a corner
+        assert_eq!(None, snap((800, 1000, 300, 60), area, 64));
+        // Already there
+        assert_eq!(None, snap((0, 24, 300, 60), area, 64));
+    }
+}
@@ -1,9 +1,16 @@
+use config;
+use headless::{format_duration, Escape};
 use mpris;
+use notify;
+use remote;
+use tray;
 
+use std::cell::RefCell;
+use std::mem;
 use std::sync::mpsc;
 use std::thread;
 use std::env::args;
-use std::error::Error;
+use std::path::Path;
 
 use gtk;
 use gtk::prelude::*;
@@ -16,6 +23,21 @@ use gdk_pixbuf::prelude::*;
 use glib;
 use cairo;
 
+mod assets;
+mod blur;
+mod fade;
+mod gestures;
+mod keys;
+mod kiosk;
+mod layout;
+mod lists;
+mod lyrics;
+mod mini;
+mod overflow;
+mod palette;
+mod scene;
+mod style;
+
 static mut GUI_INST: Option<GUI> = None;
 
 /// Start the GUI
@@ -23,100 +45,418 @@ static mut GUI_INST: Option<GUI> = None;
 /// # Safety
 ///
 /// This function may only be called once.
-pub unsafe fn start(events_tx: mpsc::Sender<mpsc::Sender<mpris::Event>>) {
+pub unsafe fn start(events_tx: mpsc::Sender<mpsc::Sender<mpris::Event>>,
+                    commands_tx: mpsc::Sender<mpris::Command>,
+                    source: config::Source, config: config::Config) {
     let application = gtk::Application::new(
-        "space.jazzpis.mprvis", gio::ApplicationFlags::empty()
+        remote::APP_ID, gio::ApplicationFlags::empty()
     ).unwrap();
 
-    application.connect_startup(move |app| build_ui(app, &events_tx));
+    // Startup only happens once, but GTK wants an `Fn`
+    let source = RefCell::new(Some(source));
+    application.connect_startup(move |app| {
+        assets::register();
+        if let Some(source) = source.borrow_mut().take() {
+            build_ui(app, &events_tx, &commands_tx, source, config.clone())
+        }
+    });
     application.connect_activate(|app| {
         GUI_INST.as_ref().unwrap().raise_window(app);
     });
 
-    application.run(&args().collect::<Vec<_>>());
+    // Our own arguments have already been parsed, and GTK would reject them
+    application.run(&args().take(1).collect::<Vec<_>>());
 }
 
 unsafe fn build_ui(app: &gtk::Application,
-                   events_tx: &mpsc::Sender<mpsc::Sender<mpris::Event>>) {
-    GUI_INST = Some(GUI::new(app, events_tx));
+                   events_tx: &mpsc::Sender<mpsc::Sender<mpris::Event>>,
+                   commands_tx: &mpsc::Sender<mpris::Command>,
+                   source: config::Source, config: config::Config) {
+    GUI_INST = Some(GUI::new(app, events_tx, commands_tx, source, config));
+    setup_actions(app);
+}
+
+/// Export the `remote::ACTIONS`, so other processes can drive the window
+fn setup_actions(app: &gtk::Application) {
+    for &name in remote::ACTIONS {
+        let action = gio::SimpleAction::new(name, None);
+        let a = app.clone();
+        action.connect_activate(move |action, _| {
+            let gui = unsafe { GUI_INST.as_mut().unwrap() };
+            match action.get_name().as_ref().map(|n| n.as_str()) {
+                Some("toggle-fullscreen") => gui.toggle_fullscreen(),
+                Some("toggle-mini") => {
+                    let mini = !gui.mini.window.is_visible();
+                    gui.set_mini(mini);
+                },
+                Some("next-player") =>
+                    gui.send_command(mpris::Command::NextPlayer),
+                Some("quit-player") => gui.send_command(mpris::Command::Quit),
+                Some("cycle-layout") => {
+                    let layout = gui.layouts.next();
+                    gui.set_layout(layout);
+                },
+                Some("show") => gui.shown_window().present(),
+                Some("hide") => gui.shown_window().hide(),
+                Some("quit") => a.quit(),
+                _ => {},
+            }
+        });
+        app.add_action(&action);
+    }
 }
 
 pub struct GUI {
     window: gtk::ApplicationWindow,
     song_title: gtk::Label,
     artist: gtk::Label,
+    /// The featured artists, when they get a line of their own
+    featured: gtk::Label,
     album: gtk::Label,
+    lyrics: lyrics::View,
     cover: gtk::DrawingArea,
-    img: Option<gdk_pixbuf::Pixbuf>,
+    scene: scene::Scene,
+    /// The previous cover while it fades out
+    fading_out: Option<scene::Scene>,
+    cover_fade: fade::Fade,
+    /// Metadata to show once the labels have faded out
+    pending_data: Option<mpris::Metadata>,
+    labels_fade: fade::Fade,
+    /// Whether we asked the frame clock for updates
+    animating: bool,
+    /// Decodes the cover that's being downloaded
+    art_loader: Option<gdk_pixbuf::PixbufLoader>,
+    /// Whether `scene` already shows what `art_loader` decoded so far
+    art_shown: bool,
+    /// The scene the cover that's being decoded replaced, to go back to if
+    /// it turns out broken
+    art_previous: Option<scene::Scene>,
+    /// Whether the cover that's being downloaded is broken, so the rest of
+    /// it is ignored
+    art_failed: bool,
     playback_status: gtk::Label,
+    player: gtk::Button,
+    player_icon: gtk::Image,
+    player_name: gtk::Label,
+    player_quit: gtk::Button,
+    previous: gtk::Button,
+    play_pause: gtk::Button,
+    play_pause_icon: gtk::Image,
+    next: gtk::Button,
+    fullscreen: bool,
+    kiosk: kiosk::Kiosk,
+    keys: keys::Keys,
+    gestures: gestures::Gestures,
+    layouts: layout::Layouts,
+    overflow: overflow::Labels,
+    mini: mini::Mini,
+    notifier: notify::Notifier,
+    /// To the tray icon's thread while it's shown
+    tray: Option<mpsc::Sender<tray::Update>>,
+    /// What's playing, for the tray icon
+    data: Option<mpris::Metadata>,
+    status: mpris::PlaybackStatus,
+    capabilities: mpris::Capabilities,
+    lists: lists::Lists,
+    style: style::Style,
+    config: config::Config,
+    source: config::Source,
     events_tx: mpsc::Sender<mpsc::Sender<mpris::Event>>,
+    commands_tx: mpsc::Sender<mpris::Command>,
 }
 
 impl GUI {
+    /// In microseconds
+    const COVER_FADE : i64 = 500_000;
+    const LABELS_FADE : i64 = 400_000;
+    /// How far the arrow keys seek, in microseconds
+    const SEEK_STEP : i64 = 5_000_000;
+    const VOLUME_STEP : f64 = 0.05;
+    /// The sizes covers are scaled down to for notifications and the tray
+    const NOTIFICATION_SIZE : i32 = 256;
+    const TRAY_ICON_SIZE : i32 = 64;
+
     pub fn new(app: &gtk::Application,
-               events_tx: &mpsc::Sender<mpsc::Sender<mpris::Event>>) -> Self {
-        let builder = gtk::Builder::new_from_file(
-            "/home/jasper/dev/mprvis/assets/gui.glade"
+               events_tx: &mpsc::Sender<mpsc::Sender<mpris::Event>>,
+               commands_tx: &mpsc::Sender<mpris::Command>,
+               source: config::Source, config: config::Config) -> Self {
+        let builder = assets::builder(
+            "gui.glade", config.assets.dir.as_deref()
         );
 
         let window: gtk::ApplicationWindow = builder.get_object("window")
                                                     .unwrap();
         window.set_application(app);
-        Self::setup_style(&window);
+        let style = style::Style::new(&config);
 
         let w = window.clone();
         window.connect_delete_event(move |_, _| {
+            if let Some(gui) = unsafe { GUI_INST.as_mut() } {
+                gui.layouts.save();
+                if gui.tray.is_some() {
+                    w.hide();
+                    return Inhibit(true);
+                }
+                // Otherwise the hidden mini window keeps the app running
+                gui.mini.window.destroy();
+            }
             w.destroy();
             Inhibit(false)
         });
+        window.connect_show(|_| {
+            if let Some(gui) = unsafe { GUI_INST.as_mut() } {
+                gui.update_tray(tray::Update::WindowShown(true));
+            }
+        });
+        window.connect_hide(|_| {
+            if let Some(gui) = unsafe { GUI_INST.as_mut() } {
+                gui.update_tray(tray::Update::WindowShown(false));
+            }
+        });
+        window.connect_window_state_event(|_, event| {
+            let gui = unsafe { GUI_INST.as_mut().unwrap() };
+            gui.fullscreen = event.get_new_window_state()
+                                  .contains(gdk::WindowState::FULLSCREEN);
+            Inhibit(false)
+        });
+        window.add_events(gdk::EventMask::POINTER_MOTION_MASK.bits() as i32);
+        window.connect_motion_notify_event(|window, _| {
+            let gui = unsafe { GUI_INST.as_mut().unwrap() };
+            gui.kiosk.moved(window);
+            Inhibit(false)
+        });
+        glib::timeout_add_seconds(kiosk::Kiosk::CHECK_INTERVAL, || {
+            if let Some(gui) = unsafe { GUI_INST.as_mut() } {
+                gui.kiosk.check_idle(&gui.window);
+            }
+            glib::Continue(true)
+        });
 
-        let song_title = builder.get_object("song_title").unwrap();
-        let artist = builder.get_object("artist").unwrap();
-        let album = builder.get_object("album").unwrap();
+        let song_title: gtk::Label = builder.get_object("song_title").unwrap();
+        let artist: gtk::Label = builder.get_object("artist").unwrap();
+        let featured = builder.get_object("featured").unwrap();
+        let album: gtk::Label = builder.get_object("album").unwrap();
+        let lyrics = lyrics::View::new(builder.get_object("lyrics").unwrap(),
+                                       &config.lyrics);
         let playback_status = builder.get_object("playback_status").unwrap();
+        let player_icon = builder.get_object("player_icon").unwrap();
+        let player_name = builder.get_object("player_name").unwrap();
 
-        let cover: gtk::DrawingArea = builder.get_object("cover").unwrap();
-        cover.connect_draw(|_, context| {
-            let gui = unsafe { GUI_INST.as_mut().unwrap() };
-            gui.draw_cover(context)
+        let player: gtk::Button = builder.get_object("player").unwrap();
+        player.connect_clicked(|_| {
+            let gui = unsafe { GUI_INST.as_ref().unwrap() };
+            gui.send_command(mpris::Command::Raise);
         });
 
+        let player_quit: gtk::Button =
+            builder.get_object("player_quit").unwrap();
+        player_quit.connect_clicked(|_| {
+            let gui = unsafe { GUI_INST.as_ref().unwrap() };
+            gui.send_command(mpris::Command::Quit);
+        });
+
+        let previous: gtk::Button = builder.get_object("previous").unwrap();
+        previous.connect_clicked(|_| {
+            let gui = unsafe { GUI_INST.as_ref().unwrap() };
+            gui.send_command(mpris::Command::Previous);
+        });
+        let play_pause: gtk::Button = builder.get_object("play_pause").unwrap();
+        play_pause.connect_clicked(|_| {
+            let gui = unsafe { GUI_INST.as_ref().unwrap() };
+            gui.send_command(mpris::Command::PlayPause);
+        });
+        let play_pause_icon = builder.get_object("play_pause_icon").unwrap();
+        let next: gtk::Button = builder.get_object("next").unwrap();
+        next.connect_clicked(|_| {
+            let gui = unsafe { GUI_INST.as_ref().unwrap() };
+            gui.send_command(mpris::Command::Next);
+        });
+
+        let lists = lists::Lists::new(&builder);
+        let layouts = layout::Layouts::new(&builder, &window);
+        let overflow = overflow::Labels::new(
+            &[song_title.clone(), artist.clone(), album.clone()], &config.labels
+        );
+
+        let cover: gtk::DrawingArea = builder.get_object("cover").unwrap();
+        let gestures = gestures::Gestures::new(&cover);
+        let mini = mini::Mini::new(&builder, app);
+        for area in &[&cover, &mini.cover] {
+            area.connect_draw(|area, context| {
+                let gui = unsafe { GUI_INST.as_mut().unwrap() };
+                gui.draw_cover(area, context)
+            });
+        }
+
+        let windows = [window.clone().upcast::<gtk::Window>(),
+                       mini.window.clone()];
+        for w in &windows {
+            w.connect_key_press_event(|_, event| {
+                let gui = unsafe { GUI_INST.as_mut().unwrap() };
+                gui.key_pressed(event)
+            });
+        }
+
         window.show_all();
+        mini.window.realize();
+        Self::connect_frame_clock(&window.clone().upcast());
+        Self::connect_frame_clock(&mini.window);
 
         let events_tx = events_tx.clone();
+        let commands_tx = commands_tx.clone();
 
-        let gui = Self {
+        let mut gui = Self {
             window,
             song_title,
             artist,
+            featured,
             album,
+            lyrics,
             playback_status,
             cover,
-            img: None,
+            scene: scene::Scene::new(None, &config.cover),
+            fading_out: None,
+            cover_fade: fade::Fade::new(Self::COVER_FADE),
+            pending_data: None,
+            labels_fade: fade::Fade::new(Self::LABELS_FADE),
+            animating: false,
+            art_loader: None,
+            art_shown: false,
+            art_previous: None,
+            art_failed: false,
+            player,
+            player_icon,
+            player_name,
+            player_quit,
+            previous,
+            play_pause,
+            play_pause_icon,
+            next,
+            fullscreen: false,
+            kiosk: kiosk::Kiosk::new(),
+            keys: keys::Keys::new(&config.keys),
+            gestures,
+            layouts,
+            overflow,
+            mini,
+            notifier: notify::Notifier::new(commands_tx.clone()),
+            tray: None,
+            data: None,
+            status: mpris::PlaybackStatus::Stopped,
+            capabilities: mpris::Capabilities::default(),
+            lists,
+            style,
+            config,
+            source,
             events_tx,
+            commands_tx,
         };
+        let kiosk = gui.config.kiosk.enabled;
+        gui.layouts.set(gui.config.layout);
+        let compact = gui.layouts.compact();
+        gui.overflow.apply(compact);
+        gui.lyrics.set_compact(compact);
+        if !kiosk {
+            gui.layouts.restore();
+        }
+        gui.kiosk.apply(&gui.window, &gui.config.kiosk, false);
+        gui.mini.idle_opacity = gui.config.mini.opacity.0;
+        gui.notifier.enabled = gui.config.notifications.enabled;
+        if gui.config.mini.enabled {
+            gui.set_mini(true);
+        }
+        let tray = gui.config.tray.clone();
+        // Before the tray icon starts, so it knows the window is hidden
+        if tray.enabled && tray.start_hidden {
+            gui.window.hide();
+        }
+        gui.set_tray(tray.enabled);
         gui.start_loop();
+        Self::watch_config();
         gui
     }
 
-    fn setup_style(win: &gtk::ApplicationWindow) {
-        let provider = gtk::CssProvider::new();
-        provider.connect_parsing_error(|_, section, error| {
-            eprintln!("CSS parsing error in lines {}--{}: {:?}",
-                      section.get_start_line(), section.get_end_line(),
-                      error.description());
+    /// Animate with the frame clock of `window`
+    fn connect_frame_clock(window: &gtk::Window) {
+        if let Some(clock) = window.get_frame_clock() {
+            // GTK's own animations update the clock too
+            clock.connect_update(|clock| {
+                if let Some(gui) = unsafe { GUI_INST.as_mut() } {
+                    let now = clock.get_frame_time();
+                    if gui.animating {
+                        gui.tick(now);
+                    }
+                    gui.overflow.tick(now);
+                    gui.lyrics.tick(now);
+                }
+            });
+        }
+    }
+
+    /// Apply changes to the config file while we're running
+    fn watch_config() {
+        glib::timeout_add_seconds(config::PolledFile::INTERVAL, || {
+            if let Some(gui) = unsafe { GUI_INST.as_mut() } {
+                if gui.source.changed() {
+                    gui.reload_config();
+                }
+            }
+            glib::Continue(true)
         });
-        if provider.load_from_path(
-            "/home/jasper/dev/mprvis/assets/gui.css"
-        ).is_ok() {
-            let style_context = win.get_style_context().unwrap();
-            style_context.add_provider(&provider, 0);
-            gtk::StyleContext::add_provider_for_screen(
-                &gdk::Screen::get_default().unwrap(),
-                &provider,
-                0,
-            );
+    }
+
+    /// Read the config again. If it's invalid, we keep the current one.
+    fn reload_config(&mut self) {
+        let config = match self.source.load() {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("{}", err);
+                return;
+            },
+        };
+
+        if config.player_name() != self.config.player_name() {
+            self.send_command(mpris::Command::SelectPlayer(
+                config.player_name().unwrap_or_else(|| {
+                    mpris::MPRIS::DEFAULT_PLAYER.to_string()
+                })
+            ));
+        }
+        if config.assets != self.config.assets
+                || config.theme != self.config.theme
+                || config.font != self.config.font {
+            self.style.apply(&config);
         }
+        if config.labels != self.config.labels {
+            self.overflow.configure(&config.labels, self.layouts.compact());
+        }
+        let featured = config.featured != self.config.featured;
+        if config.lyrics != self.config.lyrics {
+            self.lyrics.configure(&config.lyrics);
+        }
+        if config.keys != self.config.keys {
+            self.keys = keys::Keys::new(&config.keys);
+        }
+        let playing = self.status == mpris::PlaybackStatus::Playing;
+        self.kiosk.apply(&self.window, &config.kiosk, playing);
+        if config.layout != self.config.layout {
+            self.set_layout(config.layout);
+        }
+        self.mini.idle_opacity = config.mini.opacity.0;
+        self.notifier.enabled = config.notifications.enabled;
+        self.set_tray(config.tray.enabled);
+        if config.mini.enabled != self.config.mini.enabled {
+            self.set_mini(config.mini.enabled);
+        } else if self.mini.window.is_visible() {
+            self.mini.show();
+        }
+        self.config = config;
+        if featured {
+            self.refresh_labels();
+        }
+        self.scene.restyle(&self.config.cover);
+        self.style.set_palette(&self.scene.palette);
+        self.redraw_covers();
     }
 
     fn start_loop(&self) {
@@ -129,14 +469,13 @@ impl GUI {
     }
 
     fn run_loop(events_rx: mpsc::Receiver<mpris::Event>) {
-        let mut art = Vec::<u8>::new();
-        for mut ev in events_rx {
+        for ev in events_rx {
             match ev {
                 mpris::Event::Data(ref metadata) => {
                     let metadata = metadata.clone();
                     glib::idle_add(move || {
                         unsafe {
-                            GUI_INST.as_ref()
+                            GUI_INST.as_mut()
                                     .unwrap()
                                     .update_data(metadata.clone());
                         }
@@ -147,47 +486,177 @@ impl GUI {
                     let playback_status = playback_status.clone();
                     glib::idle_add(move || {
                         unsafe {
-                            GUI_INST.as_ref()
+                            GUI_INST.as_mut()
                                     .unwrap()
                                     .update_status(playback_status.clone());
                         }
                         gtk::Continue(false)
                     });
                 },
-                mpris::Event::ArtData(ref mut data) => {
-                    art.append(data);
+                mpris::Event::Player(ref info) => {
+                    let info = info.clone();
+                    glib::idle_add(move || {
+                        unsafe {
+                            GUI_INST.as_ref()
+                                    .unwrap()
+                                    .update_player(info.clone());
+                        }
+                        gtk::Continue(false)
+                    });
+                },
+                mpris::Event::Position(position) => {
+                    glib::idle_add(move || {
+                        unsafe {
+                            GUI_INST.as_mut()
+                                    .unwrap()
+                                    .lyrics
+                                    .set_position(position);
+                        }
+                        gtk::Continue(false)
+                    });
+                },
+                mpris::Event::Capabilities(capabilities) => {
+                    glib::idle_add(move || {
+                        unsafe {
+                            GUI_INST.as_mut()
+                                    .unwrap()
+                                    .update_capabilities(capabilities);
+                        }
+                        gtk::Continue(false)
+                    });
+                },
+                mpris::Event::UpNext(ref tracks) => {
+                    let tracks = tracks.clone();
+                    glib::idle_add(move || {
+                        unsafe {
+                            GUI_INST.as_mut()
+                                    .unwrap()
+                                    .lists
+                                    .update_up_next(tracks.clone());
+                        }
+                        gtk::Continue(false)
+                    });
+                },
+                mpris::Event::Thumbnail(ref id, ref data) => {
+                    let (id, data) = (id.clone(), data.clone());
+                    glib::idle_add(move || {
+                        unsafe {
+                            GUI_INST.as_mut()
+                                    .unwrap()
+                                    .lists
+                                    .update_thumbnail(id.clone(), &data);
+                        }
+                        gtk::Continue(false)
+                    });
+                },
+                mpris::Event::Playlists(ref playlists) => {
+                    let playlists = playlists.clone();
+                    glib::idle_add(move || {
+                        unsafe {
+                            GUI_INST.as_mut()
+                                    .unwrap()
+                                    .lists
+                                    .update_playlists(playlists.clone());
+                        }
+                        gtk::Continue(false)
+                    });
+                },
+                mpris::Event::ActivePlaylist(ref active) => {
+                    let active = active.clone();
+                    glib::idle_add(move || {
+                        unsafe {
+                            GUI_INST.as_ref()
+                                    .unwrap()
+                                    .lists
+                                    .update_active_playlist(active.clone());
+                        }
+                        gtk::Continue(false)
+                    });
+                },
+                mpris::Event::ArtData(ref data) => {
+                    let data = data.clone();
+                    glib::idle_add(move || {
+                        unsafe {
+                            GUI_INST.as_mut()
+                                    .unwrap()
+                                    .feed_art(&data);
+                        }
+                        gtk::Continue(false)
+                    });
                 },
                 mpris::Event::ArtDone(success) => {
-                    if success {
-                        let art = art.clone();
-                        glib::idle_add(move || {
-                            unsafe {
-                                GUI_INST.as_mut()
-                                        .unwrap()
-                                        .update_art(&art);
-                            }
-                            gtk::Continue(false)
-                        });
-                    }
-                    art = Vec::new();
-                }
+                    glib::idle_add(move || {
+                        unsafe {
+                            GUI_INST.as_mut()
+                                    .unwrap()
+                                    .finish_art(success);
+                        }
+                        gtk::Continue(false)
+                    });
+                },
             }
         }
     }
 
-    pub fn update_data(&self, data: mpris::Metadata) {
-        self.song_title.set_text(
-            &data.title.unwrap_or("No song playing!".to_string())
-        );
-        self.artist.set_text(
-            &data.artist.unwrap_or("".to_string())
-        );
+    /// Fade the labels over to `data`
+    pub fn update_data(&mut self, data: mpris::Metadata) {
+        // Past the middle of a fade, the old labels are already gone
+        if !self.labels_fade.is_running()
+                || self.labels_fade.progress() >= 0.5 {
+            self.labels_fade.restart();
+        }
+        self.notifier.update(&data);
+        self.lyrics.load(&data);
+        self.update_tray(tray::Update::Data(data.clone()));
+        self.data = Some(data.clone());
+        self.pending_data = Some(data);
+        self.animate();
+    }
+
+    fn set_labels(&self, data: mpris::Metadata) {
+        let title = data.title.unwrap_or("No song playing!".to_string());
+        self.song_title.set_text(&title);
+        self.mini.title.set_text(&title);
+
+        let config = &self.config.featured;
+        let featured = data.featured.as_ref()
+            .filter(|_| config.show != config::ShowFeatured::Hidden)
+            .map(|names| format!("feat. {}", config.join(names)));
+        let artist = data.artist.unwrap_or("".to_string());
+        // There's no room for another line in the strip
+        let inline = config.show == config::ShowFeatured::Inline
+                  || self.layouts.compact();
+        match featured {
+            Some(ref featured) if inline => {
+                self.artist.set_text(&format!("{} {}", artist, featured));
+                self.featured.hide();
+            },
+            Some(ref featured) => {
+                self.artist.set_text(&artist);
+                self.featured.set_text(featured);
+                self.featured.show();
+            },
+            None => {
+                self.artist.set_text(&artist);
+                self.featured.hide();
+            },
+        }
         self.album.set_text(
             &data.album.unwrap_or("".to_string())
         );
     }
 
-    pub fn update_status(&self, playback_status: mpris::PlaybackStatus) {
+    /// Show the current data again after how it's shown changed
+    fn refresh_labels(&self) {
+        // A fade sets them soon enough
+        if self.pending_data.is_none() {
+            if let Some(ref data) = self.data {
+                self.set_labels(data.clone());
+            }
+        }
+    }
+
+    pub fn update_status(&mut self, playback_status: mpris::PlaybackStatus) {
         let status = match playback_status {
             mpris::PlaybackStatus::Paused => "Paused",
             mpris::PlaybackStatus::Playing => "Playing",
@@ -195,43 +664,495 @@ impl GUI {
         };
 
         self.playback_status.set_text(status);
+        self.kiosk.set_playing(
+            &self.window, playback_status == mpris::PlaybackStatus::Playing
+        );
+        self.lyrics.set_playing(
+            playback_status == mpris::PlaybackStatus::Playing
+        );
+        self.update_tray(tray::Update::Playback(playback_status.clone()));
+        self.status = playback_status;
+        self.update_controls();
+    }
+
+    pub fn update_capabilities(&mut self, capabilities: mpris::Capabilities) {
+        self.capabilities = capabilities;
+        self.update_tray(tray::Update::Capabilities(capabilities));
+        self.update_controls();
+    }
+
+    /// Make the control buttons reflect the playback status and disable the
+    /// ones the player can't honor
+    fn update_controls(&self) {
+        let caps = &self.capabilities;
+        let (icon, can_toggle) = match self.status {
+            mpris::PlaybackStatus::Playing =>
+                ("media-playback-pause", caps.can_pause),
+            _ => ("media-playback-start", caps.can_play),
+        };
+        for image in &[&self.play_pause_icon, &self.mini.play_pause_icon] {
+            image.set_from_icon_name(icon, gtk::IconSize::Button.into());
+        }
+        for button in &[&self.play_pause, &self.mini.play_pause] {
+            button.set_sensitive(caps.can_control && can_toggle);
+        }
+        self.previous.set_sensitive(caps.can_control && caps.can_go_previous);
+        self.next.set_sensitive(caps.can_control && caps.can_go_next);
     }
 
-    pub fn update_art(&mut self, data: &[u8]) {
+    pub fn update_player(&self, info: mpris::PlayerInfo) {
+        self.player_name.set_text(
+            &info.identity.unwrap_or("Unknown player".to_string())
+        );
+        match info.desktop_entry.and_then(|e| Self::desktop_icon(&e)) {
+            Some(ref icon) if Path::new(icon).is_absolute() => {
+                self.player_icon.set_from_file(icon);
+            },
+            Some(ref icon) => {
+                self.player_icon.set_from_icon_name(
+                    icon.as_str(), gtk::IconSize::Button.into()
+                );
+            },
+            None => {
+                self.player_icon.set_from_icon_name(
+                    "audio-x-generic", gtk::IconSize::Button.into()
+                );
+            },
+        }
+        self.player.set_sensitive(info.can_raise);
+        self.player_quit.set_visible(info.can_quit);
+    }
+
+    /// Look up the icon of a desktop entry in the XDG data directories
+    ///
+    /// Returns either an icon name or an absolute path to the icon.
+    fn desktop_icon(desktop_entry: &str) -> Option<String> {
+        let key_file = glib::KeyFile::new();
+        key_file.load_from_data_dirs(
+            format!("applications/{}.desktop", desktop_entry),
+            glib::KeyFileFlags::NONE,
+        ).ok()?;
+        key_file.get_string("Desktop Entry", "Icon").ok()
+    }
+
+    fn send_command(&self, cmd: mpris::Command) {
+        if self.commands_tx.send(cmd).is_err() {
+            eprintln!("MPRIS thread is gone, can't send command");
+        }
+    }
+
+    /// Decode the next part of the cover, showing as much of it as we can
+    pub fn feed_art(&mut self, data: &[u8]) {
+        if self.art_failed {
+            return;
+        }
+        let loader = match self.art_loader {
+            Some(ref loader) => loader.clone(),
+            None => self.new_art_loader(),
+        };
+        if let Err(err) = loader.write(data) {
+            // Keep showing the old cover if the new one is broken
+            eprintln!("Couldn't parse image: {}", err);
+            let _ = loader.close();
+            self.art_loader = None;
+            self.art_failed = true;
+            self.restore_art();
+            return;
+        }
+        self.show_art(&loader);
+    }
+
+    pub fn finish_art(&mut self, success: bool) {
+        let mut cover = None;
+        self.art_failed = false;
+        if let Some(loader) = self.art_loader.take() {
+            if let Err(err) = loader.close() {
+                eprintln!("Couldn't parse image: {}", err);
+            }
+            if success {
+                self.show_art(&loader);
+            }
+            // Only now that it's complete, the colors can be picked
+            if success && self.art_shown {
+                self.scene.restyle(&self.config.cover);
+                self.style.set_palette(&self.scene.palette);
+                self.redraw_covers();
+                cover = self.scene.img().cloned();
+            } else {
+                self.restore_art();
+            }
+        }
+        self.art_previous = None;
+        // The art always follows the metadata, so the track is announced
+        // with its cover
+        self.notifier.loaded(cover.as_ref().and_then(|img| {
+            Self::raw_image(img, Self::NOTIFICATION_SIZE)
+        }));
+        if let Some(ref img) = cover {
+            let icon = Self::raw_image(img, Self::TRAY_ICON_SIZE);
+            self.update_tray(tray::Update::Icon(icon));
+        }
+    }
+
+    /// The pixels of `img`, scaled down to fit `size`, to send over D-Bus
+    fn raw_image(img: &gdk_pixbuf::Pixbuf, size: i32)
+                 -> Option<notify::Image> {
+        let scale = (size as f64
+                     / img.get_width().max(img.get_height()) as f64).min(1.);
+        let img = img.scale_simple(
+            (img.get_width() as f64 * scale).round() as i32,
+            (img.get_height() as f64 * scale).round() as i32,
+            gdk_pixbuf::InterpType::Bilinear
+        )?;
+        Some(notify::Image {
+            width: img.get_width(),
+            height: img.get_height(),
+            rowstride: img.get_rowstride(),
+            has_alpha: img.get_has_alpha(),
+            bits_per_sample: img.get_bits_per_sample(),
+            channels: img.get_n_channels(),
+            data: unsafe { img.get_pixels() }.to_vec(),
+        })
+    }
+
+    fn new_art_loader(&mut self) -> gdk_pixbuf::PixbufLoader {
         let loader = gdk_pixbuf::PixbufLoader::new();
-        loader.write(data);
-        loader.close();
-        self.img = loader.get_pixbuf();
-        if self.img.is_none() {
-            eprintln!("Couldn't parse image!");
+        // The parts that weren't decoded yet are undefined, so start out with
+        // the background
+        let bg = self.scene.palette.background;
+        let fill = ((bg.r * 255.) as u32) << 24 | ((bg.g * 255.) as u32) << 16
+                 | ((bg.b * 255.) as u32) << 8 | 0xff;
+        loader.connect_area_prepared(move |loader| {
+            if let Some(img) = loader.get_pixbuf() {
+                img.fill(fill);
+            }
+        });
+        let covers = (self.cover.clone(), self.mini.cover.clone());
+        loader.connect_area_updated(move |_, _, _, _, _| {
+            covers.0.queue_draw();
+            covers.1.queue_draw();
+        });
+
+        self.art_loader = Some(loader.clone());
+        self.art_shown = false;
+        loader
+    }
+
+    /// Show what `loader` decoded so far, fading over to it the first time
+    fn show_art(&mut self, loader: &gdk_pixbuf::PixbufLoader) {
+        if self.art_shown {
+            self.scene.updated();
+            return;
+        }
+        let img = match loader.get_pixbuf() {
+            Some(img) => img,
+            None => return,
+        };
+        // Keep the colors until the cover is complete
+        let scene = scene::Scene::loading(img, self.scene.palette);
+        let previous = mem::replace(&mut self.scene, scene);
+        self.art_previous = Some(previous.clone());
+        self.fading_out = Some(previous);
+        self.art_shown = true;
+        self.cover_fade.restart();
+        self.animate();
+        self.redraw_covers();
+    }
+
+    /// Fade back to the cover from before the one that failed to load
+    fn restore_art(&mut self) {
+        self.art_shown = false;
+        if let Some(previous) = self.art_previous.take() {
+            self.fading_out = Some(mem::replace(&mut self.scene, previous));
+            self.cover_fade.restart();
+            self.animate();
+            self.redraw_covers();
+        }
+    }
+
+    /// Have the frame clock call `tick` until the fades are done
+    fn animate(&mut self) {
+        if self.animating {
+            return;
+        }
+        match self.shown_window().get_frame_clock() {
+            Some(clock) => {
+                clock.begin_updating();
+                self.animating = true;
+            },
+            // Not realized, so there's nothing to animate
+            None => {
+                self.cover_fade.finish();
+                self.labels_fade.finish();
+                self.tick(0);
+            },
+        }
+    }
+
+    fn tick(&mut self, now: i64) {
+        let cover = self.cover_fade.tick(now);
+        if !cover {
+            self.fading_out = None;
+        }
+        self.redraw_covers();
+
+        let labels = self.labels_fade.tick(now);
+        let t = self.labels_fade.progress();
+        if t >= 0.5 {
+            if let Some(data) = self.pending_data.take() {
+                self.set_labels(data);
+            }
+        }
+        let opacity = (2. * t - 1.).abs();
+        for label in &[&self.song_title, &self.artist, &self.featured,
+                       &self.album] {
+            label.set_opacity(opacity);
+        }
+
+        if !cover && !labels && self.animating {
+            if let Some(clock) = self.shown_window().get_frame_clock() {
+                clock.end_updating();
+            }
+            self.animating = false;
         }
-        self.cover.queue_draw();
     }
 
     fn raise_window(&self, app: &gtk::Application) {
-        self.window.get_window().unwrap().raise();
-    }
-
-    fn draw_cover(&self, context: &cairo::Context) -> Inhibit {
-        let width = self.cover.get_allocated_width();
-        let height = self.cover.get_allocated_height();
-        let size = width.min(height);
-        let (x, y) = ((width - size) as f64 / 2., (height - size) as f64 / 2.);
-
-        context.set_source_rgb(0., 0., 0.);
-        context.paint();
-        if let Some(ref img) = self.img {
-            let img = img.scale_simple(
-                size, size, gdk_pixbuf::InterpType::Bilinear
-            ).unwrap();
-            let surf = cairo::Context::cairo_surface_create_from_pixbuf(
-                &img, 0, self.window.get_window().as_ref()
-            ).unwrap();
-            context.set_source_surface(&surf, x, y);
-        }
-        context.paint();
-        context.set_source_rgba(0., 0., 0., 0.5);
-        context.paint();
+        // Also brings the window back if it was hidden through `hide`
+        self.shown_window().present();
+    }
+
+    /// Show or remove the tray icon
+    fn set_tray(&mut self, enabled: bool) {
+        if enabled == self.tray.is_some() {
+            return;
+        }
+        if !enabled {
+            // The thread removes the icon once it notices
+            self.tray = None;
+            // Bring back a window that was hidden in the tray
+            if !self.window.is_visible() && !self.mini.window.is_visible() {
+                self.window.show();
+            }
+            return;
+        }
+
+        let on_action = |action| {
+            glib::idle_add(move || {
+                if let Some(gui) = unsafe { GUI_INST.as_mut() } {
+                    gui.tray_action(action);
+                }
+                glib::Continue(false)
+            });
+        };
+        self.tray = Some(tray::start(self.commands_tx.clone(),
+                                     Box::new(on_action)));
+        if let Some(data) = self.data.clone() {
+            self.update_tray(tray::Update::Data(data));
+        }
+        let icon = self.scene.img().and_then(|img| {
+            Self::raw_image(img, Self::TRAY_ICON_SIZE)
+        });
+        let status = self.status.clone();
+        let shown = self.window.is_visible();
+        self.update_tray(tray::Update::Icon(icon));
+        self.update_tray(tray::Update::Playback(status));
+        self.update_tray(tray::Update::Capabilities(self.capabilities));
+        self.update_tray(tray::Update::WindowShown(shown));
+    }
+
+    fn update_tray(&mut self, update: tray::Update) {
+        let sent = match self.tray {
+            Some(ref tx) => tx.send(update).is_ok(),
+            None => return,
+        };
+        if !sent {
+            // There's no tray icon after all, so don't hide the window in it
+            self.tray = None;
+        }
+    }
+
+    fn tray_action(&mut self, action: tray::Action) {
+        match action {
+            tray::Action::ToggleWindow if self.window.is_visible() =>
+                self.window.hide(),
+            tray::Action::ToggleWindow => self.window.present(),
+            tray::Action::Quit => self.quit(),
+        }
+    }
+
+    /// Run the action bound to the key in `event`, if there is one
+    fn key_pressed(&mut self, event: &gdk::EventKey) -> Inhibit {
+        let action = match self.keys.action(event.get_keyval(),
+                                            event.get_state()) {
+            Some(action) => action,
+            None => return Inhibit(false),
+        };
+        let caps = self.capabilities;
+        let command = match action {
+            keys::Action::PlayPause =>
+                Some(mpris::Command::PlayPause),
+            keys::Action::SeekForward if caps.can_seek =>
+                Some(mpris::Command::Seek(Self::SEEK_STEP)),
+            keys::Action::SeekBackward if caps.can_seek =>
+                Some(mpris::Command::Seek(-Self::SEEK_STEP)),
+            keys::Action::Next if caps.can_go_next =>
+                Some(mpris::Command::Next),
+            keys::Action::Previous if caps.can_go_previous =>
+                Some(mpris::Command::Previous),
+            keys::Action::VolumeUp =>
+                Some(mpris::Command::ChangeVolume(Self::VOLUME_STEP)),
+            keys::Action::VolumeDown =>
+                Some(mpris::Command::ChangeVolume(-Self::VOLUME_STEP)),
+            keys::Action::NextPlayer => {
+                self.send_command(mpris::Command::NextPlayer);
+                None
+            },
+            keys::Action::Fullscreen => {
+                self.toggle_fullscreen();
+                None
+            },
+            keys::Action::Quit => {
+                self.quit();
+                None
+            },
+            _ => None,
+        };
+        // The player would refuse anyway
+        if let Some(command) = command.filter(|_| caps.can_control) {
+            self.send_command(command);
+        }
+        Inhibit(true)
+    }
+
+    /// Run the action for a gesture on the cover
+    fn gesture(&mut self, action: gestures::Action) {
+        let caps = self.capabilities;
+        let command = match action {
+            gestures::Action::PlayPause => mpris::Command::PlayPause,
+            gestures::Action::Next if caps.can_go_next => mpris::Command::Next,
+            gestures::Action::Previous if caps.can_go_previous =>
+                mpris::Command::Previous,
+            gestures::Action::Volume(steps) => mpris::Command::ChangeVolume(
+                f64::from(steps) * Self::VOLUME_STEP
+            ),
+            gestures::Action::Details(x, y) => {
+                self.gestures.show_details(&self.details(), x, y);
+                return;
+            },
+            _ => return,
+        };
+        if caps.can_control {
+            self.send_command(command);
+        }
+    }
+
+    /// Pango markup describing the current track and player
+    fn details(&self) -> String {
+        let player = self.player_name.get_text().unwrap_or_default();
+        let data = match self.data {
+            Some(ref data) => data,
+            None => return Escape::Pango.apply(&player),
+        };
+        let title = data.title.as_deref().unwrap_or("No song playing!");
+        let mut lines = vec![format!("<b>{}</b>", Escape::Pango.apply(title))];
+        lines.extend([&data.artist, &data.album].iter()
+                         .filter_map(|part| part.as_ref())
+                         .map(|part| Escape::Pango.apply(part)));
+        if let Some(length) = data.length {
+            lines.push(format_duration(length));
+        }
+        lines.push(format!("<small>{}</small>", Escape::Pango.apply(&player)));
+        lines.join("\n")
+    }
+
+    fn quit(&mut self) {
+        self.layouts.save();
+        if let Some(app) = self.window.get_application() {
+            app.quit();
+        }
+    }
+
+    /// The mini window if it's used, otherwise the main one
+    fn shown_window(&self) -> gtk::Window {
+        if self.mini.window.is_visible() {
+            self.mini.window.clone()
+        } else {
+            self.window.clone().upcast()
+        }
+    }
+
+    /// Switch between the mini window and the main one
+    fn set_mini(&mut self, enabled: bool) {
+        if enabled == self.mini.window.is_visible() {
+            return;
+        }
+        // The fades run on the frame clock of the window that's shown
+        if self.animating {
+            self.cover_fade.finish();
+            self.labels_fade.finish();
+            self.tick(0);
+        }
+        if enabled {
+            self.layouts.save();
+            self.window.hide();
+            self.mini.show();
+        } else {
+            self.mini.window.hide();
+            self.window.present();
+        }
+    }
+
+    /// Switch to `layout`, and move the window to where it was the last time
+    /// it was used
+    fn set_layout(&mut self, layout: config::Layout) {
+        self.layouts.save();
+        self.layouts.set(layout);
+        self.overflow.apply(self.layouts.compact());
+        self.lyrics.set_compact(self.layouts.compact());
+        self.refresh_labels();
+        if !self.fullscreen {
+            self.layouts.restore();
+        }
+        self.redraw_covers();
+    }
+
+    fn toggle_fullscreen(&self) {
+        if self.fullscreen {
+            self.window.unfullscreen();
+        } else {
+            self.window.fullscreen();
+        }
+    }
+
+    fn redraw_covers(&self) {
+        self.cover.queue_draw();
+        self.mini.cover.queue_draw();
+    }
+
+    fn draw_cover(&mut self, area: &gtk::DrawingArea,
+                  context: &cairo::Context) -> Inhibit {
+        let width = area.get_allocated_width();
+        let height = area.get_allocated_height();
+        let window = area.get_window();
+        let config = &self.config.cover;
+        // Only the main window has labels on top of the cover
+        let darken = *area == self.cover && self.layouts.overlays_cover();
+
+        match self.fading_out {
+            Some(ref mut previous) => {
+                previous.draw(context, width, height, window.as_ref(), config,
+                              darken);
+                context.push_group();
+                self.scene.draw(context, width, height, window.as_ref(),
+                                config, darken);
+                context.pop_group_to_source();
+                context.paint_with_alpha(self.cover_fade.progress());
+            },
+            None =>
+                self.scene.draw(context, width, height, window.as_ref(),
+                                config, darken),
+        }
         Inhibit(false)
     }
 }
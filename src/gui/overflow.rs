@@ -0,0 +1,236 @@
+use config::{self, Overflow};
+use super::GUI_INST;
+
+use gtk;
+use gtk::prelude::*;
+use gdk;
+use gdk::prelude::*;
+use cairo;
+use pango;
+use pango::LayoutExt;
+
+struct Label {
+    widget: gtk::Label,
+    mode: Overflow,
+    /// The text on one line at its full size, while a marquee scrolls it
+    layout: Option<pango::Layout>,
+    /// The frame time the marquee started at
+    start: Option<i64>,
+    /// How far the marquee has scrolled, in pixels
+    offset: f64,
+    /// How much the font is shrunk
+    scale: f64,
+}
+
+/// Fits text that's too long for its label the way `config::Labels` asks
+pub struct Labels {
+    labels: Vec<Label>,
+    /// The clock we asked for updates while a marquee scrolls
+    clock: Option<gdk::FrameClock>,
+}
+
+impl Labels {
+    /// In pixels per second
+    const MARQUEE_SPEED : f64 = 40.;
+    /// How long the marquee rests at the start, in microseconds
+    const MARQUEE_PAUSE : i64 = 2_000_000;
+    /// Between the end of the text and the start of its next copy, in
+    /// pixels
+    const MARQUEE_GAP : f64 = 64.;
+    const MIN_SCALE : f64 = 0.5;
+
+    /// Handle the title, artist and album `widgets`
+    pub fn new(widgets: &[gtk::Label; 3], config: &config::Labels) -> Self {
+        for (i, widget) in widgets.iter().enumerate() {
+            widget.connect_size_allocate(move |_, _| {
+                if let Some(gui) = unsafe { GUI_INST.as_mut() } {
+                    gui.overflow.measure(i);
+                }
+            });
+            widget.connect_property_label_notify(move |_| {
+                if let Some(gui) = unsafe { GUI_INST.as_mut() } {
+                    gui.overflow.measure(i);
+                }
+            });
+            widget.connect_draw(move |_, context| {
+                match unsafe { GUI_INST.as_ref() } {
+                    Some(gui) => gui.overflow.draw(i, context),
+                    None => Inhibit(false),
+                }
+            });
+        }
+
+        let modes = [config.title, config.artist, config.album];
+        Labels {
+            labels: widgets.iter().zip(modes.iter()).map(|(widget, &mode)| {
+                Label {
+                    widget: widget.clone(),
+                    mode,
+                    layout: None,
+                    start: None,
+                    offset: 0.,
+                    scale: 1.,
+                }
+            }).collect(),
+            clock: None,
+        }
+    }
+
+    /// Use the modes from `config`
+    pub fn configure(&mut self, config: &config::Labels, compact: bool) {
+        let modes = [config.title, config.artist, config.album];
+        for (label, &mode) in self.labels.iter_mut().zip(modes.iter()) {
+            label.mode = mode;
+        }
+        self.apply(compact);
+    }
+
+    /// Set the labels up for their modes. The `compact` layout only has room
+    /// for one line, even for wrapping labels.
+    pub fn apply(&mut self, compact: bool) {
+        for label in &mut self.labels {
+            let wrap = label.mode == Overflow::Wrap && !compact;
+            label.widget.set_line_wrap(wrap);
+            label.widget.set_ellipsize(if wrap { pango::EllipsizeMode::None }
+                                       else { pango::EllipsizeMode::End });
+            if label.mode != Overflow::Shrink && label.scale != 1. {
+                label.scale = 1.;
+                label.widget.set_attributes(None);
+            }
+        }
+        for i in 0..self.labels.len() {
+            self.measure(i);
+        }
+    }
+
+    /// Check whether the text of label `i` still fits
+    fn measure(&mut self, i: usize) {
+        let label = &mut self.labels[i];
+        let available = label.widget.get_allocated_width();
+        let text = label.widget.get_text().unwrap_or_default();
+        let layout = if label.mode == Overflow::Wrap {
+            None
+        } else {
+            label.widget.create_pango_layout(text.as_str())
+        };
+        let width = layout.as_ref().map_or(0, |l| l.get_pixel_size().0);
+
+        match (label.mode, layout) {
+            (Overflow::Marquee, Some(layout))
+                    if width > available && available > 1 => {
+                let same = label.layout.as_ref().is_some_and(|old| {
+                    old.get_text().as_deref() == Some(text.as_str())
+                });
+                if !same {
+                    label.start = None;
+                    label.offset = 0.;
+                }
+                label.layout = Some(layout);
+            },
+            (Overflow::Shrink, Some(_)) => {
+                let scale = shrink_scale(width, available);
+                if scale != label.scale {
+                    label.scale = scale;
+                    let attrs = pango::AttrList::new();
+                    attrs.insert(pango::Attribute::new_scale(scale).unwrap());
+                    label.widget.set_attributes(&attrs);
+                }
+                label.layout = None;
+            },
+            _ => label.layout = None,
+        }
+        label.widget.queue_draw();
+        self.update_clock();
+    }
+
+    /// Keep the frame clock updating while a marquee scrolls
+    fn update_clock(&mut self) {
+        let scrolling = self.labels.iter().any(|l| l.layout.is_some());
+        match (scrolling, self.clock.take()) {
+            (true, None) => {
+                self.clock = self.labels[0].widget.get_frame_clock();
+                if let Some(ref clock) = self.clock {
+                    clock.begin_updating();
+                }
+            },
+            (false, Some(clock)) => clock.end_updating(),
+            (_, clock) => self.clock = clock,
+        }
+    }
+
+    /// Move the marquees to where they are at the frame time `now`
+    pub fn tick(&mut self, now: i64) {
+        for label in &mut self.labels {
+            let width = match label.layout {
+                Some(ref layout) => layout.get_pixel_size().0,
+                None => continue,
+            };
+            let start = *label.start.get_or_insert(now);
+            let offset = marquee_offset(now - start, width);
+            // Nothing moves while it rests
+            if offset != label.offset {
+                label.offset = offset;
+                label.widget.queue_draw();
+            }
+        }
+    }
+
+    /// Draw label `i` if it's a scrolling marquee
+    fn draw(&self, i: usize, context: &cairo::Context) -> Inhibit {
+        let label = &self.labels[i];
+        let layout = match label.layout {
+            Some(ref layout) => layout,
+            None => return Inhibit(false),
+        };
+        let style = label.widget.get_style_context().unwrap();
+        let (width, height) = layout.get_pixel_size();
+        let y = f64::from(label.widget.get_allocated_height() - height) / 2.;
+        // A second copy follows the first one in
+        let x = -label.offset;
+        gtk::render_layout(&style, context, x, y, layout);
+        gtk::render_layout(&style, context,
+                           x + f64::from(width) + Self::MARQUEE_GAP, y, layout);
+        Inhibit(true)
+    }
+}
+
+/// How far a marquee of text `width` pixels wide has scrolled after
+/// `elapsed` microseconds
+fn marquee_offset(elapsed: i64, width: i32) -> f64 {
+    let distance = f64::from(width) + Labels::MARQUEE_GAP;
+    let cycle = Labels::MARQUEE_PAUSE
+              + (distance / Labels::MARQUEE_SPEED * 1e6) as i64;
+    let scrolling = elapsed % cycle - Labels::MARQUEE_PAUSE;
+    if scrolling <= 0 {
+        0.
+    } else {
+        scrolling as f64 / 1e6 * Labels::MARQUEE_SPEED
+    }
+}
+
+/// How much to shrink text `width` pixels wide to fit into `available`
+fn shrink_scale(width: i32, available: i32) -> f64 {
+    if width <= available || available <= 0 {
+        1.
+    } else {
+        (f64::from(available) / f64::from(width)).max(Labels::MIN_SCALE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_scrolls_and_shrinks_long_text() {
+        // Rests, then needs 10s for 336 + 64 pixels
+        assert_eq!(0., marquee_offset(1_500_000, 336));
+        assert_eq!(40., marquee_offset(3_000_000, 336));
+        assert_eq!(0., marquee_offset(12_000_000, 336));
+        assert_eq!(20., marquee_offset(14_500_000, 336));
+
+        assert_eq!(1., shrink_scale(300, 400));
+        assert_eq!(0.75, shrink_scale(400, 300));
+        assert_eq!(0.5, shrink_scale(1000, 300));
+    }
+}
@@ -0,0 +1,270 @@
+use std::cmp::Reverse;
+
+use gdk_pixbuf;
+use gdk_pixbuf::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+}
+
+impl Color {
+    pub const BLACK : Color = Color { r: 0., g: 0., b: 0. };
+    pub const WHITE : Color = Color { r: 1., g: 1., b: 1. };
+
+    fn from_rgb(rgb: [u8; 3]) -> Self {
+        Color {
+            r: rgb[0] as f64 / 255.,
+            g: rgb[1] as f64 / 255.,
+            b: rgb[2] as f64 / 255.,
+        }
+    }
+
+    /// `self` moved `amount` of the way towards `other`
+    pub fn mix(&self, other: &Color, amount: f64) -> Color {
+        Color {
+            r: self.r + (other.r - self.r) * amount,
+            g: self.g + (other.g - self.g) * amount,
+            b: self.b + (other.b - self.b) * amount,
+        }
+    }
+
+    /// The relative luminance as defined by WCAG
+    fn luminance(&self) -> f64 {
+        fn linear(c: f64) -> f64 {
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        }
+        0.2126 * linear(self.r) + 0.7152 * linear(self.g)
+            + 0.0722 * linear(self.b)
+    }
+
+    /// The WCAG contrast ratio, between 1 and 21
+    pub fn contrast(&self, other: &Color) -> f64 {
+        let (a, b) = (self.luminance(), other.luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    fn saturation(&self) -> f64 {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        if max == 0. { 0. } else { (max - min) / max }
+    }
+
+    fn css(&self) -> String {
+        format!("rgb({}, {}, {})", (self.r * 255.).round(),
+                (self.g * 255.).round(), (self.b * 255.).round())
+    }
+}
+
+/// The average color of a group of similar pixels, and how many there were
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Swatch {
+    color: Color,
+    population: usize,
+}
+
+/// Split `pixels` into up to `2^depth` groups of similar colors by cutting
+/// along the channel with the widest range at its median
+fn median_cut(pixels: &mut [[u8; 3]], depth: u32) -> Vec<Swatch> {
+    if pixels.is_empty() {
+        return Vec::new();
+    }
+
+    let range = |c: usize| {
+        let values = pixels.iter().map(|p| p[c]);
+        values.clone().max().unwrap() - values.min().unwrap()
+    };
+    let channel = (0..3).max_by_key(|&c| range(c)).unwrap();
+    if depth == 0 || pixels.len() == 1 || range(channel) == 0 {
+        let sum = pixels.iter().fold([0usize; 3], |mut sum, p| {
+            for c in 0..3 {
+                sum[c] += p[c] as usize;
+            }
+            sum
+        });
+        let n = pixels.len();
+        let avg = |c: usize| ((sum[c] + n / 2) / n) as u8;
+        return vec![Swatch {
+            color: Color::from_rgb([avg(0), avg(1), avg(2)]),
+            population: n,
+        }];
+    }
+
+    pixels.sort_by_key(|p| p[channel]);
+    let (low, high) = pixels.split_at_mut(pixels.len() / 2);
+    let mut swatches = median_cut(low, depth - 1);
+    swatches.extend(median_cut(high, depth - 1));
+    swatches
+}
+
+/// Colors for the window, picked to match the cover
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    /// Fills the window around the cover and darkens it behind the text
+    pub background: Color,
+    pub title: Color,
+    pub text: Color,
+    /// How strongly `background` is painted over the cover
+    pub overlay: f64,
+}
+
+impl Palette {
+    /// WCAG's minimum contrast for normal text
+    const MIN_CONTRAST : f64 = 4.5;
+    /// ... and for large text like the title
+    const MIN_TITLE_CONTRAST : f64 = 3.;
+    /// Swatches covering less of the cover than this are ignored when
+    /// looking for the brightest area behind the text
+    const MIN_SHARE : f64 = 0.05;
+    /// The cover is scaled down to this size before quantizing
+    const SAMPLE_SIZE : i32 = 48;
+
+    /// The colors we use without a cover
+    pub fn plain(overlay: f64) -> Self {
+        Palette {
+            background: Color::BLACK,
+            title: Color::WHITE,
+            text: Color::from_rgb([0xcc, 0xcc, 0xcc]),
+            overlay,
+        }
+    }
+
+    pub fn from_pixbuf(pixbuf: &gdk_pixbuf::Pixbuf, min_overlay: f64) -> Self {
+        let sample = match pixbuf.scale_simple(
+            Self::SAMPLE_SIZE, Self::SAMPLE_SIZE,
+            gdk_pixbuf::InterpType::Bilinear
+        ) {
+            Some(sample) => sample,
+            None => return Self::plain(min_overlay),
+        };
+
+        let channels = sample.get_n_channels() as usize;
+        let stride = sample.get_rowstride() as usize;
+        let width = sample.get_width() as usize;
+        let data = unsafe { sample.get_pixels() };
+        let mut pixels = Vec::with_capacity(width * width);
+        for y in 0..sample.get_height() as usize {
+            for x in 0..width {
+                let i = y * stride + x * channels;
+                pixels.push([data[i], data[i + 1], data[i + 2]]);
+            }
+        }
+        Self::from_pixels(&mut pixels, min_overlay)
+    }
+
+    /// A palette for a cover made of `pixels`. The overlay is at least
+    /// `min_overlay`, but stronger if the text would be hard to read.
+    fn from_pixels(pixels: &mut [[u8; 3]], min_overlay: f64) -> Self {
+        let mut swatches = median_cut(pixels, 4);
+        if swatches.is_empty() {
+            return Self::plain(min_overlay);
+        }
+        swatches.sort_by_key(|s| Reverse(s.population));
+        let total = pixels.len() as f64;
+        let share = |s: &Swatch| s.population as f64 / total;
+
+        let dominant = swatches[0].color;
+        // Colorful, but not some speck in the corner
+        let accent = swatches.iter()
+            .max_by(|a, b| {
+                let score = |s: &Swatch| {
+                    s.color.saturation() * share(s).sqrt()
+                };
+                score(a).partial_cmp(&score(b)).unwrap()
+            })
+            .map(|s| s.color)
+            .unwrap_or(dominant);
+        // The text has to be readable even on the brightest part
+        let brightest = swatches.iter()
+            .filter(|s| share(s) >= Self::MIN_SHARE)
+            .map(|s| s.color)
+            .max_by(|a, b| a.luminance().partial_cmp(&b.luminance()).unwrap())
+            .unwrap_or(dominant);
+
+        let background = dominant.mix(&Color::BLACK, 0.75);
+        let mut overlay = min_overlay;
+        let backdrop = |overlay| brightest.mix(&background, overlay);
+        while overlay < 1.
+                && Color::WHITE.contrast(&backdrop(overlay))
+                   < Self::MIN_CONTRAST {
+            overlay = (overlay + 0.05).min(1.);
+        }
+
+        let backdrop = backdrop(overlay);
+        Palette {
+            background,
+            title: readable(accent.mix(&Color::WHITE, 0.5), &backdrop,
+                            Self::MIN_TITLE_CONTRAST),
+            text: readable(accent.mix(&Color::WHITE, 0.7), &backdrop,
+                           Self::MIN_CONTRAST),
+            overlay,
+        }
+    }
+
+    /// CSS for the labels and buttons, which are either on top of the cover
+    /// or the background
+    pub fn css(&self) -> String {
+        let text = self.text.css();
+        format!("#wrapper {{\n    background-color: {};\n}}\n\
+                 #wrapper, #player, #controls button {{\n    color: {};\n}}\n\
+                 #song_title {{\n    color: {};\n}}\n",
+                self.background.css(), text, self.title.css())
+    }
+}
+
+/// `color`, lightened until it has `min` contrast to `backdrop`
+fn readable(color: Color, backdrop: &Color, min: f64) -> Color {
+    let mut amount = 0f64;
+    let mut result = color;
+    while result.contrast(backdrop) < min && amount < 1. {
+        amount = (amount + 0.1).min(1.);
+        result = color.mix(&Color::WHITE, amount);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_splits_colors_at_the_median() {
+        let mut pixels = vec![[255, 0, 0]; 4];
+        pixels.extend(vec![[0, 0, 250]; 3]);
+        pixels.push([0, 0, 240]);
+        assert_eq!(vec![
+            Swatch { color: Color::from_rgb([0, 0, 248]), population: 4 },
+            Swatch { color: Color::from_rgb([255, 0, 0]), population: 4 },
+        ], median_cut(&mut pixels, 1));
+    }
+
+    #[test]
+    fn it_keeps_text_readable() {
+        for &rgb in &[[255, 255, 255], [255, 230, 0], [0, 0, 0], [30, 90, 200]] {
+            let mut pixels = vec![rgb; 100];
+            let palette = Palette::from_pixels(&mut pixels, 0.2);
+            let backdrop = Color::from_rgb(rgb)
+                .mix(&palette.background, palette.overlay);
+            assert!(palette.text.contrast(&backdrop) >= Palette::MIN_CONTRAST,
+                    "{:?}: {:?}", rgb, palette);
+            assert!(palette.title.contrast(&backdrop)
+                        >= Palette::MIN_TITLE_CONTRAST,
+                    "{:?}: {:?}", rgb, palette);
+            assert!(palette.overlay >= 0.2);
+        }
+    }
+
+    #[test]
+    fn it_only_darkens_bright_covers_as_needed() {
+        let mut dark = vec![[20, 20, 40]; 100];
+        assert_eq!(0.3, Palette::from_pixels(&mut dark, 0.3).overlay);
+        let mut bright = vec![[250, 250, 250]; 100];
+        assert!(Palette::from_pixels(&mut bright, 0.3).overlay > 0.3);
+    }
+}
@@ -0,0 +1,149 @@
+use config;
+use super::{blur, palette};
+
+use gdk;
+use gdk::prelude::*;
+use gdk_pixbuf;
+use gdk_pixbuf::prelude::*;
+use cairo;
+
+/// A cover and everything that's drawn with it
+#[derive(Clone)]
+pub struct Scene {
+    img: Option<gdk_pixbuf::Pixbuf>,
+    /// A blurred copy of `img` for the background, made when it's first
+    /// needed
+    blurred: Option<cairo::Surface>,
+    /// `img` scaled to the size it was last drawn at
+    scaled: Option<(i32, cairo::Surface)>,
+    pub palette: palette::Palette,
+}
+
+impl Scene {
+    pub fn new(img: Option<gdk_pixbuf::Pixbuf>, config: &config::Cover)
+               -> Self {
+        let palette = Self::palette(img.as_ref(), config);
+        Scene {
+            img,
+            blurred: None,
+            scaled: None,
+            palette,
+        }
+    }
+
+    /// A cover that's still being decoded, with the colors of the previous one
+    pub fn loading(img: gdk_pixbuf::Pixbuf, palette: palette::Palette)
+                   -> Self {
+        Scene {
+            img: Some(img),
+            blurred: None,
+            scaled: None,
+            palette,
+        }
+    }
+
+    fn palette(img: Option<&gdk_pixbuf::Pixbuf>, config: &config::Cover)
+               -> palette::Palette {
+        let overlay = config.overlay_opacity.0;
+        match img {
+            Some(img) if config.dynamic_colors =>
+                palette::Palette::from_pixbuf(img, overlay),
+            _ => palette::Palette::plain(overlay),
+        }
+    }
+
+    pub fn img(&self) -> Option<&gdk_pixbuf::Pixbuf> {
+        self.img.as_ref()
+    }
+
+    /// Pick the colors again after the settings or the cover changed
+    pub fn restyle(&mut self, config: &config::Cover) {
+        self.palette = Self::palette(self.img.as_ref(), config);
+        self.blurred = None;
+    }
+
+    /// More of the cover was decoded. Blurring is too slow to redo for every
+    /// chunk, so that waits for the `restyle` once it's complete.
+    pub fn updated(&mut self) {
+        self.scaled = None;
+    }
+
+    /// Draw the cover, `darken`ed for text on top of it
+    pub fn draw(&mut self, context: &cairo::Context, width: i32, height: i32,
+                window: Option<&gdk::Window>, config: &config::Cover,
+                darken: bool) {
+        let size = width.min(height);
+        let (x, y) = ((width - size) as f64 / 2., (height - size) as f64 / 2.);
+
+        let background = self.palette.background;
+        context.set_source_rgb(background.r, background.g, background.b);
+        context.paint();
+        if config.background == config::Background::Blur {
+            self.draw_blurred(context, width, height, window);
+        }
+        if let Some(surf) = self.scaled(size, window) {
+            context.set_source_surface(surf, x, y);
+            context.paint();
+        }
+        if darken {
+            context.set_source_rgba(background.r, background.g, background.b,
+                                    self.palette.overlay);
+            context.paint();
+        }
+    }
+
+    /// `img` scaled to `size`, scaled again only when the size changes
+    fn scaled(&mut self, size: i32, window: Option<&gdk::Window>)
+              -> Option<&cairo::Surface> {
+        if size <= 0 {
+            return None;
+        }
+        if self.scaled.as_ref().map(|&(s, _)| s) != Some(size) {
+            self.scaled = self.img.as_ref()
+                .and_then(|img| img.scale_simple(
+                    size, size, gdk_pixbuf::InterpType::Bilinear
+                ))
+                .and_then(|img| {
+                    cairo::Context::cairo_surface_create_from_pixbuf(
+                        &img, 0, window
+                    )
+                })
+                .map(|surf| (size, surf));
+        }
+        self.scaled.as_ref().map(|&(_, ref surf)| surf)
+    }
+
+    /// Fill the whole area with the blurred cover, darkened so the sharp one
+    /// stands out
+    fn draw_blurred(&mut self, context: &cairo::Context, width: i32,
+                    height: i32, window: Option<&gdk::Window>) {
+        if self.blurred.is_none() {
+            self.blurred = self.img.as_ref()
+                .and_then(blur::blurred)
+                .and_then(|img| {
+                    cairo::Context::cairo_surface_create_from_pixbuf(
+                        &img, 0, window
+                    )
+                });
+        }
+        let surf = match self.blurred {
+            Some(ref surf) => surf,
+            None => return,
+        };
+
+        // Scale the square to cover the area, cropping the sides
+        let side = width.max(height) as f64;
+        let scale = side / blur::SIZE as f64;
+        context.save();
+        context.translate((width as f64 - side) / 2.,
+                          (height as f64 - side) / 2.);
+        context.scale(scale, scale);
+        context.set_source_surface(surf, 0., 0.);
+        context.paint();
+        context.restore();
+
+        let background = self.palette.background;
+        context.set_source_rgba(background.r, background.g, background.b, 0.5);
+        context.paint();
+    }
+}
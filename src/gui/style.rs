@@ -0,0 +1,181 @@
+use super::{assets, palette, GUI_INST};
+use config;
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use gtk;
+use gtk::prelude::*;
+use gdk;
+use glib;
+
+/// A stylesheet that's reloaded when its file changes
+struct Sheet {
+    provider: gtk::CssProvider,
+    /// The file to reload the sheet from, if any
+    file: config::PolledFile,
+}
+
+/// A provider for the whole screen that reports parsing errors
+fn add_provider(priority: u32) -> gtk::CssProvider {
+    let provider = gtk::CssProvider::new();
+    provider.connect_parsing_error(|_, section, error| {
+        eprintln!("CSS parsing error in lines {}--{}: {}",
+                  section.get_start_line(), section.get_end_line(), error);
+    });
+    gtk::StyleContext::add_provider_for_screen(
+        &gdk::Screen::get_default().unwrap(),
+        &provider,
+        priority,
+    );
+    provider
+}
+
+impl Sheet {
+    fn new(priority: u32) -> Self {
+        Sheet {
+            provider: add_provider(priority),
+            file: config::PolledFile::new(None),
+        }
+    }
+
+    fn set_path(&mut self, path: Option<PathBuf>) {
+        if path.as_deref() != self.file.path() {
+            self.file = config::PolledFile::new(path);
+            self.load();
+        }
+    }
+
+    /// Load the file again if it changed. A file that was removed leaves the
+    /// sheet empty.
+    fn reload_if_changed(&mut self) {
+        if self.file.changed() {
+            self.load();
+        }
+    }
+
+    fn load(&mut self) {
+        let exists = self.file.loading();
+        let res = match self.file.path() {
+            Some(path) if exists =>
+                self.provider.load_from_path(&path.to_string_lossy()),
+            _ => self.provider.load_from_data(b""),
+        };
+        if let Err(err) = res {
+            eprintln!("Couldn't load {:?}: {}", self.file.path(), err);
+        }
+    }
+}
+
+/// The stylesheets on top of GTK's theme: our default one, the colors from
+/// the cover, optionally a named theme from `themes/<name>.css` in the asset
+/// directories, the fonts from the config, and the user's
+/// `$XDG_CONFIG_HOME/mprvis/style.css`
+pub struct Style {
+    default: gtk::CssProvider,
+    palette: gtk::CssProvider,
+    theme: Sheet,
+    font: gtk::CssProvider,
+    user: Sheet,
+}
+
+impl Style {
+    pub fn new(config: &config::Config) -> Self {
+        let mut user = Sheet::new(gtk::STYLE_PROVIDER_PRIORITY_USER);
+        user.set_path(glib::get_user_config_dir()
+            .map(|dir| dir.join("mprvis").join("style.css")));
+
+        let mut style = Style {
+            default: add_provider(0),
+            // Themes can still pick their own colors
+            palette: add_provider(1),
+            theme: Sheet::new(gtk::STYLE_PROVIDER_PRIORITY_APPLICATION),
+            font: add_provider(gtk::STYLE_PROVIDER_PRIORITY_APPLICATION + 1),
+            user,
+        };
+        style.apply(config);
+
+        glib::timeout_add_seconds(config::PolledFile::INTERVAL, || {
+            if let Some(gui) = unsafe { GUI_INST.as_mut() } {
+                gui.style.reload_changed();
+            }
+            glib::Continue(true)
+        });
+
+        style
+    }
+
+    /// Use the assets, theme and fonts from `config`
+    pub fn apply(&mut self, config: &config::Config) {
+        let dir = config.assets.dir.as_deref();
+
+        // Not reloaded, as it's usually the embedded one
+        if let Err(err) = assets::load_css(&self.default, "gui.css", dir) {
+            eprintln!("Couldn't load the default style: {}", err);
+        }
+
+        let theme = config.theme.as_ref().and_then(|theme| {
+            let name = format!("themes/{}.css", theme);
+            let path = assets::find(&name, dir);
+            if path.is_none() {
+                eprintln!("Couldn't find the theme {:?} ({})", theme, name);
+            }
+            path
+        });
+        self.theme.set_path(theme);
+
+        if let Err(err) = self.font.load_from_data(
+            font_css(&config.font).as_bytes()
+        ) {
+            eprintln!("Couldn't apply the fonts: {}", err);
+        }
+    }
+
+    pub fn set_palette(&self, palette: &palette::Palette) {
+        if let Err(err) = self.palette.load_from_data(palette.css().as_bytes()) {
+            eprintln!("Couldn't apply the colors: {}", err);
+        }
+    }
+
+    fn reload_changed(&mut self) {
+        self.theme.reload_if_changed();
+        self.user.reload_if_changed();
+    }
+}
+
+/// CSS for the fonts set in the config
+fn font_css(font: &config::Font) -> String {
+    let mut css = String::new();
+    if font.family.is_some() || font.size.is_some() {
+        css.push_str("#wrapper {\n");
+        if let Some(ref family) = font.family {
+            let family = family.replace('\\', "\\\\").replace('"', "\\\"");
+            let _ = writeln!(css, "    font-family: \"{}\";", family);
+        }
+        if let Some(config::Positive(size)) = font.size {
+            let _ = writeln!(css, "    font-size: {}pt;", size);
+        }
+        css.push_str("}\n");
+    }
+    if let Some(config::Positive(size)) = font.title_size {
+        let _ = writeln!(css, "#song_title {{\n    font-size: {}pt;\n}}", size);
+    }
+    css
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_generates_font_css() {
+        assert_eq!("", font_css(&config::Font::default()));
+        assert_eq!("#wrapper {\n    font-family: \"Cantarell \\\"Light\\\"\";\n\
+                    }\n#song_title {\n    font-size: 32.5pt;\n}\n",
+                   font_css(&config::Font {
+                       family: Some("Cantarell \"Light\"".to_string()),
+                       size: None,
+                       title_size: Some(config::Positive(32.5)),
+                   }));
+    }
+}
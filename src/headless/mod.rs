@@ -0,0 +1,459 @@
+mod template;
+
+pub use self::template::{format_duration, Escape, Template};
+
+use config;
+use mpris;
+use xdg;
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use dbus;
+use serde_json;
+
+/// How events are printed
+#[derive(Debug, Clone)]
+pub enum Format {
+    /// One human-readable line per event
+    Text,
+    /// One JSON object with the current state per event
+    Json,
+    /// A line rendered from the template whenever it changes
+    Template(Template),
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ if s.contains('{') => s.parse().map(Format::Template),
+            _ => Err(format!("Unknown format {:?}, expected text, json or a \
+                              template like \"{{artist}} - {{title}}\"", s)),
+        }
+    }
+}
+
+impl Format {
+    /// Whether the output shows the art, so it has to be downloaded
+    pub fn uses_art(&self) -> bool {
+        match *self {
+            Format::Text => false,
+            Format::Json => true,
+            Format::Template(ref template) => template.uses("art"),
+        }
+    }
+}
+
+/// What we know about the player, accumulated from the events
+#[derive(Default)]
+struct State {
+    metadata: Option<mpris::Metadata>,
+    status: Option<mpris::PlaybackStatus>,
+    /// The last reported position and when it was reported
+    position: Option<(i64, Instant)>,
+    /// Whether to collect and save the art
+    keep_art: bool,
+    art: Vec<u8>,
+    art_path: Option<PathBuf>,
+    /// How many covers are kept in the cache directory
+    max_art: usize,
+    player: Option<String>,
+}
+
+impl State {
+    fn new(cache: &config::Cache) -> Self {
+        State {
+            max_art: cache.art,
+            ..State::default()
+        }
+    }
+
+    fn update(&mut self, ev: &mpris::Event) {
+        match *ev {
+            mpris::Event::Data(ref data) => {
+                self.metadata = Some(data.clone());
+                self.art_path = None;
+            },
+            mpris::Event::Playback(ref status) => {
+                // Don't extrapolate over the time before the status changed
+                self.position = self.position().map(|p| (p, Instant::now()));
+                self.status = Some(status.clone());
+            },
+            mpris::Event::Position(position) => {
+                self.position = Some((position, Instant::now()));
+            },
+            mpris::Event::ArtData(ref data) if self.keep_art => {
+                self.art.extend_from_slice(data);
+            },
+            mpris::Event::ArtDone(success) if self.keep_art => {
+                if success {
+                    self.art_path = self.save_art();
+                }
+                self.art = Vec::new();
+            },
+            mpris::Event::Player(ref info) => {
+                self.player = info.identity.clone();
+            },
+            _ => {},
+        }
+    }
+
+    /// The current position in microseconds, extrapolated from the last
+    /// reported one
+    fn position(&self) -> Option<i64> {
+        self.position.map(|(position, at)| {
+            match self.status {
+                Some(mpris::PlaybackStatus::Playing) => {
+                    let elapsed = at.elapsed();
+                    position + elapsed.as_secs() as i64 * 1_000_000
+                             + elapsed.subsec_micros() as i64
+                },
+                _ => position,
+            }
+        })
+    }
+
+    /// The value of a template field, if it's known
+    fn field(&self, name: &str) -> Option<String> {
+        let metadata = self.metadata.as_ref();
+        match name {
+            "title" => metadata.and_then(|m| m.title.clone()),
+            "artist" => metadata.and_then(|m| m.artist.clone()),
+            "album" => metadata.and_then(|m| m.album.clone()),
+            "featured" => metadata.and_then(|m| m.featured.as_ref())
+                                  .map(|f| f.join(", ")),
+            "art" => self.art_path.as_ref()
+                                  .map(|p| p.to_string_lossy().into_owned()),
+            "status" => self.status.as_ref().map(|s| s.to_string()),
+            "position" => self.position().map(template::format_duration),
+            "length" => metadata.and_then(|m| m.length)
+                                .map(template::format_duration),
+            "player" => self.player.clone(),
+            _ => None,
+        }
+    }
+
+    fn is_playing(&self) -> bool {
+        match self.status {
+            Some(mpris::PlaybackStatus::Playing) => true,
+            _ => false,
+        }
+    }
+
+    /// Write the art to the cache directory, so other programs can show it
+    fn save_art(&self) -> Option<PathBuf> {
+        let url = self.metadata.as_ref().and_then(|m| m.art.as_ref())?;
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+
+        let dir = xdg::cache_dir()?.join("art");
+        let path = dir.join(format!("{:016x}", hasher.finish()));
+        if !path.exists() {
+            if let Err(err) = fs::create_dir_all(&dir)
+                                  .and_then(|_| fs::write(&path, &self.art)) {
+                eprintln!("Couldn't save art to {:?}: {}", path, err);
+                return None;
+            }
+            if let Err(err) = Self::prune(&dir, self.max_art.max(1)) {
+                eprintln!("Couldn't clean up {:?}: {}", dir, err);
+            }
+        }
+        Some(path)
+    }
+
+    /// Remove all but the `max` newest files in `dir`
+    fn prune(dir: &Path, max: usize) -> io::Result<()> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            files.push((entry.metadata()?.modified()?, entry.path()));
+        }
+        files.sort_by(|a, b| b.cmp(a));
+        for (_, path) in files.iter().skip(max) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+}
+
+/// The JSON object printed for each event
+#[derive(Serialize)]
+struct Snapshot<'a> {
+    event: &'a mpris::Event,
+    metadata: &'a Option<mpris::Metadata>,
+    status: &'a Option<mpris::PlaybackStatus>,
+    /// Position in seconds
+    position: Option<f64>,
+    art: &'a Option<PathBuf>,
+}
+
+/// Print the events from the `MPRIS` thread to stdout, one line per event
+pub fn run(events_rx: mpsc::Receiver<mpris::Event>, format: Format,
+           config: &config::Config) {
+    let stdout = io::stdout();
+    let state = State::new(&config.cache);
+    if let Err(err) = write_events(events_rx, format, state,
+                                   &mut stdout.lock()) {
+        eprintln!("Couldn't write to stdout: {}", err);
+    }
+}
+
+fn write_events<W: Write>(events_rx: mpsc::Receiver<mpris::Event>,
+                          format: Format, mut state: State, out: &mut W)
+                          -> io::Result<()> {
+    state.keep_art = format.uses_art();
+    if let Format::Template(template) = format {
+        return run_template(events_rx, template, state, out);
+    }
+
+    for ev in events_rx {
+        state.update(&ev);
+        let line = match format {
+            Format::Text => describe(&ev),
+            Format::Json => to_json(&ev, &state),
+            Format::Template(_) => unreachable!(),
+        };
+        if let Some(line) = line {
+            writeln!(out, "{}", line)?;
+            out.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Print the rendered template whenever it changes. While playing, the
+/// position is re-rendered every second.
+fn run_template<W: Write>(events_rx: mpsc::Receiver<mpris::Event>,
+                          template: Template, mut state: State, out: &mut W)
+                          -> io::Result<()> {
+    let ticks = template.uses("position");
+    let mut last = None;
+    loop {
+        if ticks && state.is_playing() {
+            match events_rx.recv_timeout(Duration::from_secs(1)) {
+                Ok(ev) => state.update(&ev),
+                Err(mpsc::RecvTimeoutError::Timeout) => {},
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        } else {
+            match events_rx.recv() {
+                Ok(ev) => state.update(&ev),
+                Err(_) => break,
+            }
+        }
+
+        if state.metadata.is_none() {
+            continue;
+        }
+        let line = template.render(|field| state.field(field));
+        if last.as_ref() != Some(&line) {
+            writeln!(out, "{}", line)?;
+            out.flush()?;
+            last = Some(line);
+        }
+    }
+    Ok(())
+}
+
+/// Print the player's current state once, rendered with `template`
+pub fn print_status(mpris: &mpris::MPRIS, template: &Template)
+                    -> Result<(), dbus::Error> {
+    let mut state = State::default();
+    state.update(&mpris::Event::Player(mpris.get_player_info()));
+    state.update(&mpris::Event::Playback(mpris.get_status()?));
+    state.update(&mpris::Event::Data(mpris.get_current()?));
+    if let Some(position) = mpris.get_position() {
+        state.update(&mpris::Event::Position(position));
+    }
+    println!("{}", template.render(|field| state.field(field)));
+    Ok(())
+}
+
+fn to_json(ev: &mpris::Event, state: &State) -> Option<String> {
+    match *ev {
+        // The art is announced once it's done, thumbnails aren't interesting
+        mpris::Event::ArtData(_) | mpris::Event::Thumbnail(_, _) => None,
+        _ => {
+            let snapshot = Snapshot {
+                event: ev,
+                metadata: &state.metadata,
+                status: &state.status,
+                position: state.position().map(|p| p as f64 / 1e6),
+                art: &state.art_path,
+            };
+            serde_json::to_string(&snapshot).map_err(|err| {
+                eprintln!("Couldn't serialize {:?}: {}", ev, err);
+            }).ok()
+        },
+    }
+}
+
+fn describe(ev: &mpris::Event) -> Option<String> {
+    match *ev {
+        mpris::Event::Data(ref data) => Some(format!("track: {}", data)),
+        mpris::Event::Playback(ref status) => Some(format!("status: {}", status)),
+        mpris::Event::Position(position) => Some(format!(
+            "position: {}", position as f64 / 1e6
+        )),
+        mpris::Event::Player(ref info) => Some(format!(
+            "player: {}",
+            info.identity.as_ref().map(|i| i.as_str()).unwrap_or("Unknown")
+        )),
+        mpris::Event::Capabilities(ref caps) => {
+            let names = [
+                (caps.can_control, "control"),
+                (caps.can_play, "play"),
+                (caps.can_pause, "pause"),
+                (caps.can_seek, "seek"),
+                (caps.can_go_next, "next"),
+                (caps.can_go_previous, "previous"),
+            ];
+            let names : Vec<_> = names.iter()
+                                      .filter(|&&(can, _)| can)
+                                      .map(|&(_, name)| name)
+                                      .collect();
+            Some(format!("capabilities: {}", names.join(" ")))
+        },
+        mpris::Event::UpNext(ref tracks) => Some(format!(
+            "up next: {}",
+            tracks.iter()
+                  .map(|t| t.data.to_string())
+                  .collect::<Vec<_>>()
+                  .join("; ")
+        )),
+        mpris::Event::Playlists(ref playlists) => Some(format!(
+            "playlists: {}",
+            playlists.iter()
+                     .map(|p| p.name.as_str())
+                     .collect::<Vec<_>>()
+                     .join("; ")
+        )),
+        mpris::Event::ActivePlaylist(ref active) => Some(format!(
+            "playlist: {}",
+            active.as_ref().map(|p| p.name.as_str()).unwrap_or("")
+        )),
+        mpris::Event::ArtDone(success) => Some(format!(
+            "art: {}", if success { "loaded" } else { "unavailable" }
+        )),
+        mpris::Event::ArtData(_) | mpris::Event::Thumbnail(_, _) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    #[test]
+    fn it_prints_json_snapshots() {
+        let mut state = State::default();
+        let data = mpris::Event::Data(mpris::Metadata {
+            title: Some("Brother".to_string()),
+            album: Some("In Bocca Al Lupo".to_string()),
+            artist: Some("Murder By Death".to_string()),
+            featured: None,
+            art: None,
+            length: Some(230_853_000),
+            url: None,
+            lyrics: None,
+        });
+        state.update(&data);
+        state.update(&mpris::Event::Playback(mpris::PlaybackStatus::Paused));
+        let position = mpris::Event::Position(1_500_000);
+        state.update(&position);
+
+        assert_eq!(
+            "{\"event\":{\"type\":\"Position\",\"data\":1500000},\
+             \"metadata\":{\"title\":\"Brother\",\
+             \"album\":\"In Bocca Al Lupo\",\
+             \"artist\":\"Murder By Death\",\"featured\":null,\"art\":null,\
+             \"length\":230853000,\"url\":null},\
+             \"status\":\"Paused\",\"position\":1.5,\"art\":null}",
+            to_json(&position, &state).unwrap()
+        );
+        assert_eq!(None, to_json(&mpris::Event::ArtData(vec![1, 2]), &state));
+    }
+
+    #[test]
+    fn it_only_prints_json_lines() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(mpris::Event::Playback(mpris::PlaybackStatus::Playing))
+          .unwrap();
+        tx.send(mpris::Event::ArtData(b"not json\n".to_vec())).unwrap();
+        tx.send(mpris::Event::ArtDone(false)).unwrap();
+        tx.send(mpris::Event::Thumbnail("/t/1".to_string(), vec![1, 2]))
+          .unwrap();
+        tx.send(mpris::Event::Position(0)).unwrap();
+        drop(tx);
+
+        let mut out = Vec::new();
+        write_events(rx, Format::Json, State::default(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(3, out.lines().count());
+        for line in out.lines() {
+            assert!(serde_json::from_str::<serde_json::Value>(line).is_ok(),
+                    "Not JSON: {:?}", line);
+        }
+    }
+
+    #[test]
+    fn it_prunes_the_oldest_art() {
+        let dir = env::temp_dir()
+            .join(format!("mprvis-prune-test-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (i, name) in ["a", "b", "c"].iter().enumerate() {
+            let path = dir.join(name);
+            fs::write(&path, name).unwrap();
+            let modified = ::std::time::UNIX_EPOCH
+                + Duration::from_secs(60 * i as u64);
+            fs::File::options().write(true).open(&path).unwrap()
+                .set_modified(modified).unwrap();
+        }
+
+        State::prune(&dir, 2).unwrap();
+        let mut left : Vec<_> = fs::read_dir(&dir).unwrap()
+                                   .map(|e| e.unwrap().file_name())
+                                   .collect();
+        left.sort();
+        assert_eq!(vec!["b", "c"], left);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn it_renders_templates_from_state() {
+        let mut state = State::default();
+        state.update(&mpris::Event::Data(mpris::Metadata {
+            title: Some("Yossl Yossl".to_string()),
+            album: None,
+            artist: Some("David Orlowsky Trio".to_string()),
+            featured: Some(vec!["David Orlowsky".to_string(),
+                                "Nellie Casman".to_string()]),
+            art: None,
+            length: Some(167_933_000),
+            url: None,
+            lyrics: None,
+        }));
+        state.update(&mpris::Event::Playback(mpris::PlaybackStatus::Paused));
+        state.update(&mpris::Event::Position(61_500_000));
+
+        let template : Template =
+            "{artist} – {title}{? feat. {featured}}{? ({album})} \
+             [{position}/{length}] {status}".parse().unwrap();
+        assert_eq!("David Orlowsky Trio – Yossl Yossl feat. David Orlowsky, \
+                    Nellie Casman [1:01/2:47] Paused",
+                   template.render(|field| state.field(field)));
+    }
+}
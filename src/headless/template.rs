@@ -0,0 +1,294 @@
+use std::str::FromStr;
+
+/// How substituted values are escaped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Escape {
+    None,
+    /// Escape for use in Pango markup
+    Pango,
+    /// Quote for use as a single shell word
+    Shell,
+}
+
+impl FromStr for Escape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Escape::None),
+            "pango" => Ok(Escape::Pango),
+            "shell" => Ok(Escape::Shell),
+            _ => Err(format!("Unknown escaping {:?}", s)),
+        }
+    }
+}
+
+impl Escape {
+    pub fn apply(&self, s: &str) -> String {
+        match *self {
+            Escape::None => s.to_string(),
+            Escape::Pango => {
+                let mut escaped = String::with_capacity(s.len());
+                for c in s.chars() {
+                    match c {
+                        '&' => escaped.push_str("&amp;"),
+                        '<' => escaped.push_str("&lt;"),
+                        '>' => escaped.push_str("&gt;"),
+                        '\'' => escaped.push_str("&apos;"),
+                        '"' => escaped.push_str("&quot;"),
+                        _ => escaped.push(c),
+                    }
+                }
+                escaped
+            },
+            Escape::Shell => format!("'{}'", s.replace('\'', "'\\''")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    /// A field, optionally truncated to a number of characters
+    Field(String, Option<usize>),
+    /// Only shown if all fields inside it are set
+    Section(Vec<Part>),
+}
+
+/// A format string like `{artist} - {title}{? ({album})}`
+///
+/// * `{field}` is replaced by the field's value, or nothing if it isn't set
+/// * `{field:20}` truncates the value to 20 characters
+/// * `{? ...}` is only shown if all fields inside it are set
+/// * `\{`, `\}` and `\\` are literal braces and backslashes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+    pub escape: Escape,
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars().enumerate().peekable();
+        let parts = Self::parse_parts(&mut chars, false)?;
+        Ok(Template {
+            parts,
+            escape: Escape::None,
+        })
+    }
+}
+
+impl Template {
+    /// Fields that can be used in a template
+    pub const FIELDS : &'static [&'static str] = &[
+        "title", "artist", "album", "featured", "art", "status", "position",
+        "length", "player",
+    ];
+
+    /// Render the template, looking up field values with `lookup`
+    pub fn render<F: Fn(&str) -> Option<String>>(&self, lookup: F) -> String {
+        let mut out = String::new();
+        self.render_parts(&self.parts, &lookup, &mut out);
+        out
+    }
+
+    /// Whether the template contains the field anywhere
+    pub fn uses(&self, field: &str) -> bool {
+        fn uses(parts: &[Part], field: &str) -> bool {
+            parts.iter().any(|part| match *part {
+                Part::Field(ref name, _) => name == field,
+                Part::Section(ref parts) => uses(parts, field),
+                Part::Text(_) => false,
+            })
+        }
+        uses(&self.parts, field)
+    }
+
+    /// Returns whether all fields were set
+    fn render_parts<F: Fn(&str) -> Option<String>>(&self, parts: &[Part],
+                                                   lookup: &F, out: &mut String)
+                                                   -> bool {
+        let mut complete = true;
+        for part in parts {
+            match *part {
+                Part::Text(ref text) => out.push_str(text),
+                Part::Field(ref name, max_len) => {
+                    match lookup(name).and_then(|v| {
+                        if v.len() > 0 { Some(v) } else { None }
+                    }) {
+                        Some(value) => {
+                            let value = match max_len {
+                                Some(max_len) => truncate(&value, max_len),
+                                None => value,
+                            };
+                            out.push_str(&self.escape.apply(&value));
+                        },
+                        None => complete = false,
+                    }
+                },
+                Part::Section(ref parts) => {
+                    let mut section = String::new();
+                    if self.render_parts(parts, lookup, &mut section) {
+                        out.push_str(&section);
+                    }
+                },
+            }
+        }
+        complete
+    }
+
+    fn parse_parts<I>(chars: &mut ::std::iter::Peekable<I>, in_section: bool)
+                      -> Result<Vec<Part>, String>
+        where I: Iterator<Item = (usize, char)> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, c)) => text.push(c),
+                    None => return Err(format!("Trailing backslash at {}", i)),
+                },
+                '{' => {
+                    if text.len() > 0 {
+                        parts.push(Part::Text(text));
+                        text = String::new();
+                    }
+                    if let Some(&(_, '?')) = chars.peek() {
+                        chars.next();
+                        let section = Self::parse_parts(chars, true)?;
+                        parts.push(Part::Section(section));
+                    } else {
+                        parts.push(Self::parse_field(chars, i)?);
+                    }
+                },
+                '}' if in_section => {
+                    if text.len() > 0 {
+                        parts.push(Part::Text(text));
+                    }
+                    return Ok(parts);
+                },
+                '}' => return Err(format!("Unmatched '}}' at {}", i)),
+                _ => text.push(c),
+            }
+        }
+        if in_section {
+            return Err("Unclosed '{?' section".to_string());
+        }
+        if text.len() > 0 {
+            parts.push(Part::Text(text));
+        }
+        Ok(parts)
+    }
+
+    fn parse_field<I>(chars: &mut I, start: usize) -> Result<Part, String>
+        where I: Iterator<Item = (usize, char)> {
+        let mut field = String::new();
+        loop {
+            match chars.next() {
+                Some((_, '}')) => break,
+                Some((_, c)) => field.push(c),
+                None => return Err(format!("Unclosed '{{' at {}", start)),
+            }
+        }
+
+        let mut split = field.splitn(2, ':');
+        let name = split.next().unwrap_or("").trim().to_string();
+        if !Self::FIELDS.contains(&name.as_str()) {
+            return Err(format!("Unknown field {:?} at {}, expected one of {}",
+                               name, start, Self::FIELDS.join(", ")));
+        }
+        let max_len = match split.next() {
+            Some(len) => Some(len.trim().parse().map_err(|_| {
+                format!("Invalid length {:?} for field {:?}", len, name)
+            })?),
+            None => None,
+        };
+        Ok(Part::Field(name, max_len))
+    }
+}
+
+/// Shorten `s` to at most `max_len` characters, ending it with an ellipsis
+fn truncate(s: &str, max_len: usize) -> String {
+    if s.chars().count() <= max_len {
+        s.to_string()
+    } else if max_len == 0 {
+        String::new()
+    } else {
+        let mut truncated : String = s.chars().take(max_len - 1).collect();
+        truncated.push('…');
+        truncated
+    }
+}
+
+/// Format microseconds as `m:ss`, or `h:mm:ss` for long durations
+pub fn format_duration(micros: i64) -> String {
+    let secs = micros.max(0) / 1_000_000;
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(field: &str) -> Option<String> {
+        match field {
+            "title" => Some("Yossl Yossl".to_string()),
+            "artist" => Some("David Orlowsky Trio".to_string()),
+            "album" => Some("Klezmer <Kings> & 'Queens'".to_string()),
+            "position" => Some(format_duration(61_500_000)),
+            "length" => Some(format_duration(167_933_000)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn it_renders_fields() {
+        let template : Template =
+            "{artist} – {title} [{position}/{length}]".parse().unwrap();
+        assert_eq!("David Orlowsky Trio – Yossl Yossl [1:01/2:47]",
+                   template.render(lookup));
+        assert!(template.uses("position"));
+        assert!(!template.uses("album"));
+    }
+
+    #[test]
+    fn it_renders_sections_only_if_complete() {
+        let template : Template =
+            "{title}{? feat. {featured}}{? ({album:10})}\\{\\}".parse().unwrap();
+        assert_eq!("Yossl Yossl (Klezmer <…){}", template.render(lookup));
+    }
+
+    #[test]
+    fn it_escapes_values() {
+        let mut template : Template = "<b>{album}</b>".parse().unwrap();
+        template.escape = Escape::Pango;
+        assert_eq!("<b>Klezmer &lt;Kings&gt; &amp; &apos;Queens&apos;</b>",
+                   template.render(lookup));
+        template.escape = Escape::Shell;
+        assert_eq!("<b>'Klezmer <Kings> & '\\''Queens'\\'''</b>",
+                   template.render(lookup));
+    }
+
+    #[test]
+    fn it_rejects_invalid_templates() {
+        assert!("{title".parse::<Template>().is_err());
+        assert!("{? {title}".parse::<Template>().is_err());
+        assert!("title}".parse::<Template>().is_err());
+        assert!("{nope}".parse::<Template>().is_err());
+        assert!("{title:x}".parse::<Template>().is_err());
+    }
+
+    #[test]
+    fn it_formats_durations() {
+        assert_eq!("0:00", format_duration(0));
+        assert_eq!("2:47", format_duration(167_933_000));
+        assert_eq!("1:01:01", format_duration(3_661_000_000));
+    }
+}
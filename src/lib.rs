@@ -1,11 +1,24 @@
+#[cfg(feature = "gui")]
 extern crate cairo;
 extern crate dbus;
+#[cfg(feature = "gui")]
 extern crate gdk;
+#[cfg(feature = "gui")]
 extern crate gdk_pixbuf;
+#[cfg(feature = "gui")]
 extern crate gio;
+#[cfg(feature = "gui")]
 extern crate glib;
+#[cfg(feature = "gui")]
 extern crate gtk;
+#[cfg(feature = "gui")]
+extern crate pango;
 extern crate curl;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
 
 use self::dbus::{Connection, BusType};
 
@@ -13,9 +26,17 @@ pub fn init() -> Connection {
     Connection::get_private(BusType::Session).unwrap()
 }
 
+pub mod config;
 pub mod mpris;
+#[cfg(feature = "gui")]
 pub mod gui;
+pub mod headless;
+pub mod lyrics;
+pub mod notify;
+pub mod remote;
+pub mod tray;
 mod art;
+mod xdg;
 
 #[cfg(test)]
 mod tests {
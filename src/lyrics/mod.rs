@@ -0,0 +1,293 @@
+use config;
+use mpris;
+
+use std::fs;
+use std::path::PathBuf;
+use std::str;
+
+/// A line of lyrics, sung from `time` on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    /// In microseconds
+    pub time: i64,
+    pub text: String,
+}
+
+/// Lyrics, synced to the track if they came with LRC time tags
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lyrics {
+    /// Ordered by time
+    pub lines: Vec<Line>,
+    /// Whether the lines have times, otherwise they're all at 0
+    pub synced: bool,
+}
+
+impl Lyrics {
+    /// Parse LRC, or plain text if there are no time tags. `None` if there
+    /// are no lyrics in `text`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut lines = Vec::new();
+        let mut plain = Vec::new();
+        // In milliseconds, positive to show the lines earlier
+        let mut offset = 0;
+        for raw in text.lines() {
+            let mut rest = raw.trim();
+            let mut times = Vec::new();
+            let mut tags = 0;
+            while rest.starts_with('[') {
+                let end = match rest.find(']') {
+                    Some(end) => end,
+                    None => break,
+                };
+                let tag = &rest[1..end];
+                if let Some(time) = parse_time(tag) {
+                    times.push(time);
+                } else if let Some((key, value)) = split_tag(tag) {
+                    if key == "offset" {
+                        offset = value.trim().parse().unwrap_or(offset);
+                    }
+                } else {
+                    // Brackets in the lyrics themselves
+                    break;
+                }
+                tags += 1;
+                rest = rest[end + 1..].trim_start();
+            }
+
+            let text = strip_word_times(rest);
+            if !times.is_empty() {
+                lines.extend(times.into_iter().map(|time| Line {
+                    time,
+                    text: text.clone(),
+                }));
+            } else if tags == 0 {
+                plain.push(text);
+            }
+        }
+
+        if !lines.is_empty() {
+            for line in &mut lines {
+                line.time -= offset * 1000;
+            }
+            // Lines sung again list all their times
+            lines.sort_by_key(|line| line.time);
+            return Some(Lyrics { lines, synced: true });
+        }
+
+        let start = plain.iter().position(|line| !line.is_empty())?;
+        let end = plain.iter().rposition(|line| !line.is_empty())? + 1;
+        Some(Lyrics {
+            lines: plain.drain(start..end)
+                        .map(|text| Line { time: 0, text })
+                        .collect(),
+            synced: false,
+        })
+    }
+
+    /// The line that's sung at `position`, in microseconds
+    pub fn current(&self, position: i64) -> Option<usize> {
+        if !self.synced {
+            return None;
+        }
+        self.lines.partition_point(|line| line.time <= position)
+                  .checked_sub(1)
+    }
+
+    /// When the line after the one at `position` starts
+    pub fn next_time(&self, position: i64) -> Option<i64> {
+        if !self.synced {
+            return None;
+        }
+        let next = self.lines.partition_point(|line| line.time <= position);
+        self.lines.get(next).map(|line| line.time)
+    }
+}
+
+/// A time tag like `01:23.45`, `01:23.456` or `01:23`, in microseconds
+fn parse_time(tag: &str) -> Option<i64> {
+    let (minutes, seconds) = tag.split_at(tag.find(':')?);
+    let seconds = &seconds[1..];
+    let (seconds, fraction) = match seconds.find(['.', ':']) {
+        Some(i) => (&seconds[..i], &seconds[i + 1..]),
+        None => (seconds, ""),
+    };
+    let digits = |s: &str| !s.is_empty()
+                           && s.chars().all(|c| c.is_ascii_digit());
+    if !digits(minutes) || !digits(seconds)
+            || !(fraction.is_empty() || digits(fraction))
+            || fraction.len() > 6 {
+        return None;
+    }
+    let minutes : i64 = minutes.parse().ok()?;
+    let seconds : i64 = seconds.parse().ok()?;
+    let fraction : i64 = format!("{:0<6}", fraction).parse().ok()?;
+    Some((minutes * 60 + seconds) * 1_000_000 + fraction)
+}
+
+/// An ID tag like `ar:Artist`
+fn split_tag(tag: &str) -> Option<(&str, &str)> {
+    let colon = tag.find(':')?;
+    let key = &tag[..colon];
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphabetic()) {
+        Some((key, &tag[colon + 1..]))
+    } else {
+        None
+    }
+}
+
+/// Remove the times of single words from enhanced LRC, like `<00:12.34>`
+fn strip_word_times(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        let end = match rest[start..].find('>') {
+            Some(len) if parse_time(&rest[start + 1..start + len])
+                             .is_some() => start + len,
+            _ => break,
+        };
+        stripped.push_str(&rest[..start]);
+        rest = &rest[end + 1..];
+    }
+    stripped.push_str(rest);
+    stripped.trim().to_string()
+}
+
+/// Look for the lyrics of `data`: an LRC file next to the track or in the
+/// lyrics directory, otherwise the ones the player sent
+pub fn find(config: &config::Lyrics, data: &mpris::Metadata)
+            -> Option<Lyrics> {
+    candidates(config, data).into_iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .filter_map(|text| Lyrics::parse(&text))
+        .next()
+        .or_else(|| data.lyrics.as_ref().and_then(|text| Lyrics::parse(text)))
+}
+
+/// Where LRC files for `data` could be, in order
+fn candidates(config: &config::Lyrics, data: &mpris::Metadata)
+              -> Vec<PathBuf> {
+    let track = data.url.as_ref().and_then(|url| local_path(url));
+    let mut paths = Vec::new();
+    if let Some(ref track) = track {
+        paths.push(track.with_extension("lrc"));
+    }
+    if let Some(ref dir) = config.dir {
+        if let (Some(artist), Some(title)) = (&data.artist, &data.title) {
+            let name = format!("{} - {}.lrc", artist, title);
+            paths.push(dir.join(name.replace('/', "_")));
+        }
+        if let Some(stem) = track.as_ref().and_then(|t| t.file_stem()) {
+            paths.push(dir.join(format!("{}.lrc", stem.to_string_lossy())));
+        }
+    }
+    paths
+}
+
+/// The path of a `file://` URL
+fn local_path(url: &str) -> Option<PathBuf> {
+    let path = url.strip_prefix("file://")?;
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    if !path.starts_with('/') {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = if byte == b'%' && tail.len() >= 2 {
+            str::from_utf8(&tail[..2]).ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match escaped {
+            Some(escaped) => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            },
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            },
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(time: i64, text: &str) -> Line {
+        Line { time, text: text.to_string() }
+    }
+
+    #[test]
+    fn it_parses_lrc() {
+        let lyrics = Lyrics::parse(
+            "[ar:Murder By Death]\n\
+             [ti:Brother]\n\
+             [offset:+500]\n\
+             \n\
+             [00:12.50]Like a <00:13.00>brother\n\
+             [00:20.00][01:05.123]I'll see you [there]\n\
+             [00:31]\n\
+             [00:24.10]And again\n"
+        ).unwrap();
+        assert!(lyrics.synced);
+        assert_eq!(vec![
+            line(12_000_000, "Like a brother"),
+            line(19_500_000, "I'll see you [there]"),
+            line(23_600_000, "And again"),
+            line(30_500_000, ""),
+            line(64_623_000, "I'll see you [there]"),
+        ], lyrics.lines);
+
+        assert_eq!(None, lyrics.current(11_999_999));
+        assert_eq!(Some(0), lyrics.current(12_000_000));
+        assert_eq!(Some(2), lyrics.current(25_000_000));
+        assert_eq!(Some(4), lyrics.current(300_000_000));
+        assert_eq!(Some(19_500_000), lyrics.next_time(12_000_000));
+        assert_eq!(None, lyrics.next_time(64_623_000));
+    }
+
+    #[test]
+    fn it_falls_back_to_plain_text() {
+        let lyrics = Lyrics::parse("\n[Chorus]\nLike a brother\n\n").unwrap();
+        assert!(!lyrics.synced);
+        assert_eq!(vec![line(0, "[Chorus]"), line(0, "Like a brother")],
+                   lyrics.lines);
+        assert_eq!(None, lyrics.current(10_000_000));
+
+        assert_eq!(None, Lyrics::parse("[ar:Nobody]\n\n"));
+        assert_eq!(Some(83_450_000), parse_time("01:23.45"));
+        assert_eq!(None, parse_time("ar:01"));
+    }
+
+    #[test]
+    fn it_looks_next_to_local_tracks() {
+        let mut data = mpris::Metadata {
+            title: Some("AC/DC".to_string()),
+            album: None,
+            artist: Some("Someone".to_string()),
+            featured: None,
+            art: None,
+            length: None,
+            url: Some("file:///music/Some%20One/01%20AC%E2%81%84DC.flac"
+                      .to_string()),
+            lyrics: None,
+        };
+        let config = config::Lyrics {
+            enabled: true,
+            dir: Some(PathBuf::from("/lyrics")),
+        };
+        assert_eq!(vec![
+            PathBuf::from("/music/Some One/01 AC⁄DC.lrc"),
+            PathBuf::from("/lyrics/Someone - AC_DC.lrc"),
+            PathBuf::from("/lyrics/01 AC⁄DC.lrc"),
+        ], candidates(&config, &data));
+
+        data.url = Some("https://example.com/track.mp3".to_string());
+        assert_eq!(vec![PathBuf::from("/lyrics/Someone - AC_DC.lrc")],
+                   candidates(&config, &data));
+    }
+}
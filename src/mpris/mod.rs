@@ -1,6 +1,8 @@
 use art;
 
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::mpsc;
 use std::thread;
 use std::str::FromStr;
@@ -10,7 +12,12 @@ use dbus::{Connection, BusType};
 use dbus::arg::{self, RefArg};
 use dbus::stdintf::org_freedesktop_dbus::Properties;
 
-#[derive(Debug, Clone)]
+mod tracklist;
+mod playlists;
+pub use self::tracklist::Track;
+pub use self::playlists::Playlist;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub enum PlaybackStatus {
     Playing,
     Paused,
@@ -30,114 +37,609 @@ impl FromStr for PlaybackStatus {
     }
 }
 
-#[derive(Debug, Clone)]
+impl fmt::Display for PlaybackStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            PlaybackStatus::Playing => "Playing",
+            PlaybackStatus::Paused => "Paused",
+            PlaybackStatus::Stopped => "Stopped",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data")]
 pub enum Event {
     Data(Metadata),
     Playback(PlaybackStatus),
+    /// The playback position in microseconds, sent whenever it jumps or
+    /// playback starts or stops
+    Position(i64),
+    #[serde(skip_serializing)]
     ArtData(Vec<u8>),
     ArtDone(bool),
+    Player(PlayerInfo),
+    Capabilities(Capabilities),
+    /// The tracks following the current one in the player's track list
+    UpNext(Vec<Track>),
+    /// Art for a track in the track list, by track ID
+    #[serde(skip_serializing)]
+    Thumbnail(String, Vec<u8>),
+    Playlists(Vec<Playlist>),
+    ActivePlaylist(Option<Playlist>),
 }
 
-#[derive(Debug, PartialEq, Clone, Eq, Hash)]
+/// Commands that can be sent to the `MPRIS` thread
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// Bring the player's window to the front
+    Raise,
+    /// Ask the player to quit
+    Quit,
+    PlayPause,
+    Play,
+    Pause,
+    Next,
+    Previous,
+    /// Seek forward (or backward if negative) by microseconds
+    Seek(i64),
+    /// Jump to a position in the current track, in microseconds
+    SetPosition(i64),
+    /// Skip to the track with the given ID in the track list
+    GoTo(String),
+    /// Start playing the playlist with the given ID
+    ActivatePlaylist(String),
+    /// Switch to the next player on the bus
+    NextPlayer,
+    /// Switch to the player with the given bus name
+    SelectPlayer(String),
+    /// Raise (or lower if negative) the volume, which goes from 0 to 1
+    ChangeVolume(f64),
+}
+
+#[derive(Debug, PartialEq, Clone, Eq, Hash, Serialize)]
 pub struct Metadata {
     pub title: Option<String>,
     pub album: Option<String>,
     pub artist: Option<String>,
     pub featured: Option<Vec<String>>,
     pub art: Option<String>,
+    /// Track length in microseconds
+    pub length: Option<i64>,
+    pub url: Option<String>,
+    /// Lyrics the player knows, from `xesam:asText`. Left out of the JSON,
+    /// which is printed again with every event.
+    #[serde(skip_serializing)]
+    pub lyrics: Option<String>,
+}
+
+impl fmt::Display for Metadata {
+    /// Formats as "Artist - Title (Album)", leaving out missing parts
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref artist) = self.artist {
+            write!(f, "{} - ", artist)?;
+        }
+        f.write_str(self.title.as_ref().map(|t| t.as_str()).unwrap_or("Unknown"))?;
+        if let Some(ref album) = self.album {
+            write!(f, " ({})", album)?;
+        }
+        Ok(())
+    }
+}
+
+/// Information from the `org.mpris.MediaPlayer2` root interface
+#[derive(Debug, PartialEq, Clone, Eq, Hash, Serialize)]
+pub struct PlayerInfo {
+    pub identity: Option<String>,
+    pub desktop_entry: Option<String>,
+    pub can_raise: bool,
+    pub can_quit: bool,
+    pub has_track_list: bool,
+}
+
+/// What the player allows us to do, from the `Can*` properties of
+/// `org.mpris.MediaPlayer2.Player`
+#[derive(Debug, PartialEq, Clone, Copy, Eq, Hash, Default, Serialize)]
+pub struct Capabilities {
+    pub can_control: bool,
+    pub can_play: bool,
+    pub can_pause: bool,
+    pub can_seek: bool,
+    pub can_go_next: bool,
+    pub can_go_previous: bool,
 }
 
 pub struct MPRIS {
     connection: dbus::Connection,
+    /// Bus name of the selected player
+    player: RefCell<String>,
     tx: mpsc::Sender<Event>,
-    art_tx: mpsc::Sender<Metadata>,
+    /// `None` if art isn't wanted
+    art_tx: Option<mpsc::Sender<art::Request>>,
+    commands_rx: mpsc::Receiver<Command>,
+    capabilities: Cell<Capabilities>,
+    has_track_list: Cell<bool>,
+    tracklist: RefCell<tracklist::TrackList>,
 }
 
 impl MPRIS {
+    pub const DEFAULT_PLAYER : &'static str = "org.mpris.MediaPlayer2.spotify";
+    const PLAYER_PREFIX : &'static str = "org.mpris.MediaPlayer2.";
+    const PATH : &'static str = "/org/mpris/MediaPlayer2";
+    const ROOT_INTERFACE : &'static str = "org.mpris.MediaPlayer2";
+    const PLAYER_INTERFACE : &'static str = "org.mpris.MediaPlayer2.Player";
+
     const SIGNAL : &'static str =
-        "type='signal',sender='org.mpris.MediaPlayer2.spotify',\
-         interface='org.freedesktop.DBus.Properties',\
-         member='PropertiesChanged',path='/org/mpris/MediaPlayer2',\
-         arg0='org.mpris.MediaPlayer2.Player'";
+        "interface='org.freedesktop.DBus.Properties',\
+         member='PropertiesChanged',path='/org/mpris/MediaPlayer2'";
+    const SEEKED_SIGNAL : &'static str =
+        "interface='org.mpris.MediaPlayer2.Player',\
+         member='Seeked',path='/org/mpris/MediaPlayer2'";
+    const PROPERTIES_INTERFACE : &'static str =
+        "org.freedesktop.DBus.Properties";
+
+    fn new(connection: dbus::Connection, player: Option<String>,
+           tx: mpsc::Sender<Event>,
+           art_tx: Option<mpsc::Sender<art::Request>>,
+           commands_rx: mpsc::Receiver<Command>) -> MPRIS {
+        let player = player.unwrap_or_else(|| Self::DEFAULT_PLAYER.to_string());
+        MPRIS {
+            connection,
+            player: RefCell::new(player),
+            tx,
+            art_tx,
+            commands_rx,
+            capabilities: Cell::new(Capabilities::default()),
+            has_track_list: Cell::new(false),
+            tracklist: RefCell::new(tracklist::TrackList::default()),
+        }
+    }
 
-    pub fn start(tx: mpsc::Sender<Event>) {
-        let art_tx = art::start(tx.clone());
+    /// Connect to the player (or the default one) without starting the
+    /// thread, e.g. to `execute` a single command. Fails if the player isn't
+    /// running.
+    pub fn connect(player: Option<String>) -> Result<MPRIS, dbus::Error> {
+        // Nothing is listening for events or commands
+        let (tx, _) = mpsc::channel();
+        let (_, commands_rx) = mpsc::channel();
+        let mpris = MPRIS::new(Connection::get_private(BusType::Session)?,
+                               player, tx, None, commands_rx);
+
+        let running = {
+            let player = mpris.player.borrow();
+            mpris.get_players()?.iter().any(|p| *p == *player)
+        };
+        if !running {
+            return Err(dbus::Error::new_custom(
+                "org.freedesktop.DBus.Error.ServiceUnknown",
+                &format!("{} is not running", mpris.player.borrow())
+            ));
+        }
+        Ok(mpris)
+    }
+
+    /// Start the thread, showing `player` or the default one. Art is only
+    /// fetched if `fetch_art` is set, the events for it are sent to `tx` as
+    /// well.
+    pub fn start(tx: mpsc::Sender<Event>,
+                 commands_rx: mpsc::Receiver<Command>,
+                 player: Option<String>, fetch_art: bool) {
+        let art_tx = if fetch_art {
+            Some(art::start(tx.clone()))
+        } else {
+            None
+        };
 
         let tx = tx.clone();
 
         thread::spawn(move || {
-            let mpris = MPRIS {
-                connection: Connection::get_private(BusType::Session).unwrap(),
-                tx,
-                art_tx,
-            };
+            let mpris = MPRIS::new(
+                Connection::get_private(BusType::Session).unwrap(),
+                player, tx, art_tx, commands_rx
+            );
 
-            if mpris.tx.send(Event::Playback(mpris.get_status())).is_err() {
-                return;
+            for rule in mpris.match_rules() {
+                mpris.connection.add_match(&rule).unwrap();
             }
-            let data = mpris.get_current();
-            mpris.art_tx.send(data.clone()).unwrap();
-            if mpris.tx.send(Event::Data(data)).is_err() {
+            if mpris.send_state().is_err() {
                 return;
             }
-            mpris.connection.add_match(Self::SIGNAL).unwrap();
 
             'main: loop {
-                for ci in mpris.connection.iter(1000) {
+                for ci in mpris.connection.iter(100) {
                     if let dbus::ConnectionItem::Signal(sig) = ci {
-                        if sig.headers() == (
-                            dbus::MessageType::Signal,
-                            Some("/org/mpris/MediaPlayer2".to_string()),
-                            Some("org.freedesktop.DBus.Properties".to_string()),
-                            Some("PropertiesChanged".to_string()),
-                        ) {
-                            if mpris.props_changed(sig).is_err() {
-                                break 'main;
-                            }
+                        if mpris.signal(sig).is_err() {
+                            break 'main;
                         }
                     }
+                    for cmd in mpris.commands_rx.try_iter() {
+                        mpris.handle_command(cmd);
+                    }
                 }
             }
         });
     }
 
+    /// Send everything we know about the selected player
+    fn send_state(&self) -> Result<(), mpsc::SendError<Event>> {
+        let info = self.get_player_info();
+        self.has_track_list.set(info.has_track_list);
+        self.tx.send(Event::Player(info))?;
+        match self.get_status() {
+            Ok(status) => self.tx.send(Event::Playback(status))?,
+            Err(err) => eprintln!("Couldn't get the playback status: {}",
+                                  err.message().unwrap_or("Unknown error")),
+        }
+        self.send_position()?;
+        self.capabilities.set(self.get_capabilities());
+        self.tx.send(Event::Capabilities(self.capabilities.get()))?;
+        self.send_current()?;
+        if self.has_track_list.get() {
+            self.load_tracklist()?;
+        }
+        self.load_playlists()
+    }
+
+    /// Send the metadata of the current track and fetch its art
+    fn send_current(&self) -> Result<(), mpsc::SendError<Event>> {
+        let data = match self.get_current() {
+            Ok(data) => data,
+            Err(err) => {
+                eprintln!("Couldn't get the metadata: {}",
+                          err.message().unwrap_or("Unknown error"));
+                return Ok(());
+            },
+        };
+        // Send the data before requesting the art, so the art never arrives
+        // before the metadata it belongs to
+        self.tx.send(Event::Data(data.clone()))?;
+        self.request_art(art::Request::Current(data));
+        Ok(())
+    }
+
+    /// Match rules for the signals of the selected player
+    fn match_rules(&self) -> Vec<String> {
+        let rules = [
+            Self::SIGNAL, Self::SEEKED_SIGNAL, Self::TRACKLIST_SIGNAL,
+            Self::PLAYLISTS_SIGNAL,
+        ];
+        rules.iter()
+             .map(|rule| format!("type='signal',sender='{}',{}",
+                                 self.player.borrow(), rule))
+             .collect()
+    }
+
+    /// The bus names of all MPRIS players, sorted
+    pub fn get_players(&self) -> Result<Vec<String>, dbus::Error> {
+        let bus = self.connection.with_path("org.freedesktop.DBus", "/", 500);
+        let mut reply = bus.method_call_with_args(
+            &"org.freedesktop.DBus".into(), &"ListNames".into(), |_| {}
+        )?;
+        let names : Vec<String> = reply.as_result()?.read1().map_err(|err| {
+            dbus::Error::new_custom("org.freedesktop.DBus.Error.InvalidArgs",
+                                    &format!("{:?}", err))
+        })?;
+
+        let mut players : Vec<_> =
+            names.into_iter()
+                 .filter(|name| name.starts_with(Self::PLAYER_PREFIX))
+                 .collect();
+        players.sort();
+        Ok(players)
+    }
+
+    /// Switch to the player following the selected one, skipping the ones
+    /// that don't answer
+    pub fn next_player(&self) -> Result<(), dbus::Error> {
+        let players = self.get_players()?;
+        let current = self.player.borrow().clone();
+        let start = players.iter()
+                           .position(|p| *p == current)
+                           .map_or(0, |i| i + 1);
+        let next = players.iter()
+                          .cycle()
+                          .skip(start)
+                          .take(players.len())
+                          .filter(|p| **p != current);
+        for player in next {
+            match self.select_player(player.clone()) {
+                Ok(()) => break,
+                Err(err) => eprintln!("Skipping {}: {}", player,
+                                      err.message().unwrap_or("Unknown error")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Switch to `name`, staying with the selected player if it doesn't
+    /// answer, e.g. because it quit in the meantime
+    pub fn select_player(&self, name: String) -> Result<(), dbus::Error> {
+        let previous = self.player.borrow().clone();
+        self.listen_to(name)?;
+        if let Err(err) = self.get_status() {
+            self.listen_to(previous)?;
+            return Err(err);
+        }
+
+        self.capabilities.set(Capabilities::default());
+        self.tracklist.borrow_mut().replace(Vec::new(), None);
+        // Clear what the new player might not replace. If nobody is
+        // listening anymore, the main loop stops on the next signal.
+        let _ = self.tx.send(Event::UpNext(Vec::new()))
+                       .and_then(|_| self.tx.send(Event::Playlists(Vec::new())))
+                       .and_then(|_| self.tx.send(Event::ActivePlaylist(None)))
+                       .and_then(|_| self.send_state());
+        Ok(())
+    }
+
+    /// Make `name` the selected player and only match its signals
+    fn listen_to(&self, name: String) -> Result<(), dbus::Error> {
+        for rule in self.match_rules() {
+            self.connection.remove_match(&rule)?;
+        }
+        *self.player.borrow_mut() = name;
+        for rule in self.match_rules() {
+            self.connection.add_match(&rule)?;
+        }
+        Ok(())
+    }
+
+    /// A proxy for the selected player's object
+    fn proxy<'a>(&'a self) -> dbus::ConnPath<'a, &'a dbus::Connection> {
+        self.connection.with_path(self.player.borrow().clone(), Self::PATH, 500)
+    }
+
+    fn signal(&self, sig: dbus::Message) -> Result<(), mpsc::SendError<Event>> {
+        let (_, path, interface, member) = sig.headers();
+        if path.as_ref().map(|p| p.as_str()) != Some(Self::PATH) {
+            return Ok(());
+        }
+        let (interface, member) = match (interface, member) {
+            (Some(interface), Some(member)) => (interface, member),
+            _ => return Ok(()),
+        };
+
+        if interface == Self::PROPERTIES_INTERFACE &&
+           member == "PropertiesChanged" {
+            match sig.get1::<String>() {
+                Some(ref i) if i == Self::ROOT_INTERFACE =>
+                    self.player_changed(),
+                Some(ref i) if i == Self::PLAYER_INTERFACE =>
+                    self.props_changed(sig),
+                Some(ref i) if i == Self::PLAYLISTS_INTERFACE =>
+                    self.load_playlists(),
+                _ => Ok(()),
+            }
+        } else if interface == Self::PLAYER_INTERFACE && member == "Seeked" {
+            match sig.get1::<i64>() {
+                Some(position) => self.tx.send(Event::Position(position)),
+                None => Ok(()),
+            }
+        } else if interface == Self::TRACKLIST_INTERFACE {
+            if self.has_track_list.get() {
+                self.tracklist_changed(&member, &sig)
+            } else {
+                Ok(())
+            }
+        } else if interface == Self::PLAYLISTS_INTERFACE &&
+                  member == "PlaylistChanged" {
+            self.playlist_changed(&sig)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn player_changed(&self) -> Result<(), mpsc::SendError<Event>> {
+        let info = self.get_player_info();
+        self.has_track_list.set(info.has_track_list);
+        self.tx.send(Event::Player(info))
+    }
+
     fn props_changed(&self, sig: dbus::Message)
                      -> Result<(), mpsc::SendError<Event>> {
-        let raw = sig.get2::<String,
-                             HashMap<String, arg::Variant<Box<arg::RefArg>>>>()
-            .1.unwrap();
+        let (_, raw) = sig.get2::<String,
+                             HashMap<String, arg::Variant<Box<arg::RefArg>>>>();
+        let raw = match raw {
+            Some(raw) => raw,
+            None => {
+                eprintln!("Couldn't read the changed properties");
+                return Ok(());
+            },
+        };
         if let Some(status) = raw.get("PlaybackStatus") {
-            self.tx.send(Event::Playback(
-                PlaybackStatus::from_str(status.as_str().unwrap()).unwrap()
-            ))?;
+            match status.as_str().map(PlaybackStatus::from_str) {
+                Some(Ok(status)) => self.tx.send(Event::Playback(status))?,
+                Some(Err(err)) => eprintln!("{}", err),
+                None => eprintln!("PlaybackStatus isn't a string"),
+            }
+        }
+        if raw.contains_key("PlaybackStatus") || raw.contains_key("Metadata") {
+            self.send_position()?;
+        }
+
+        let capabilities = Self::parse_capabilities(
+            &raw, self.capabilities.get()
+        );
+        if capabilities != self.capabilities.get() {
+            self.capabilities.set(capabilities);
+            self.tx.send(Event::Capabilities(capabilities))?;
         }
 
         if raw.contains_key("Metadata") {
             // We could parse the message itself... But it's incredibly
             // difficult due to dbus-rs's type system, so just fetch it again
-            let data = self.get_current();
-            self.art_tx.send(data.clone()).unwrap(); // TODO: ? operator
-            self.tx.send(Event::Data(data))?;
+            self.send_current()?;
+            if self.has_track_list.get() {
+                self.current_track_changed()?;
+            }
         }
 
         Ok(())
     }
 
-    pub fn get_status(&self) -> PlaybackStatus {
-        let player = self.connection.with_path("org.mpris.MediaPlayer2.spotify",
-                                               "/org/mpris/MediaPlayer2", 500);
-        let status : String = player.get("org.mpris.MediaPlayer2.Player",
-                                         "PlaybackStatus").unwrap();
-        PlaybackStatus::from_str(status.as_str()).unwrap()
+    fn request_art(&self, req: art::Request) {
+        if let Some(ref art_tx) = self.art_tx {
+            if art_tx.send(req).is_err() {
+                eprintln!("Can't request art, art thread is gone");
+            }
+        }
+    }
+
+    fn handle_command(&self, cmd: Command) {
+        if let Err(err) = self.execute(&cmd) {
+            eprintln!("Error while sending {:?}: {:?}", cmd, err);
+        }
+    }
+
+    pub fn execute(&self, cmd: &Command) -> Result<(), dbus::Error> {
+        match *cmd {
+            Command::Raise => self.raise(),
+            Command::Quit => self.quit(),
+            Command::PlayPause => self.call(Self::PLAYER_INTERFACE, "PlayPause"),
+            Command::Play => self.call(Self::PLAYER_INTERFACE, "Play"),
+            Command::Pause => self.call(Self::PLAYER_INTERFACE, "Pause"),
+            Command::Next => self.call(Self::PLAYER_INTERFACE, "Next"),
+            Command::Previous => self.call(Self::PLAYER_INTERFACE, "Previous"),
+            Command::Seek(offset) => self.seek(offset),
+            Command::SetPosition(position) => self.set_position(position),
+            Command::GoTo(ref id) => self.go_to(id),
+            Command::ActivatePlaylist(ref id) => self.activate_playlist(id),
+            Command::NextPlayer => self.next_player(),
+            Command::SelectPlayer(ref name) => self.select_player(name.clone()),
+            Command::ChangeVolume(delta) => self.change_volume(delta),
+        }
+    }
+
+    /// Call a method without arguments on the player
+    fn call(&self, interface: &str, method: &str) -> Result<(), dbus::Error> {
+        let player = self.proxy();
+        player.method_call_with_args(&interface.into(), &method.into(), |_| {})
+              .map(|_| ())
+    }
+
+    pub fn seek(&self, offset: i64) -> Result<(), dbus::Error> {
+        let player = self.proxy();
+        player.method_call_with_args(
+            &Self::PLAYER_INTERFACE.into(), &"Seek".into(), |msg| {
+                arg::IterAppend::new(msg).append(offset);
+            }
+        ).map(|_| ())
+    }
+
+    /// Jump to `position` in the current track. The player needs to set a
+    /// track ID for this.
+    pub fn set_position(&self, position: i64) -> Result<(), dbus::Error> {
+        let id = self.get_current_track_id().ok_or_else(|| {
+            dbus::Error::new_custom("org.freedesktop.DBus.Error.Failed",
+                                    "The current track has no ID")
+        })?;
+        let id = dbus::Path::new(id).map_err(|err| {
+            dbus::Error::new_custom("org.freedesktop.DBus.Error.InvalidArgs",
+                                    &err)
+        })?;
+        let player = self.proxy();
+        player.method_call_with_args(
+            &Self::PLAYER_INTERFACE.into(), &"SetPosition".into(), |msg| {
+                let mut args = arg::IterAppend::new(msg);
+                args.append(id);
+                args.append(position);
+            }
+        ).map(|_| ())
+    }
+
+    pub fn change_volume(&self, delta: f64) -> Result<(), dbus::Error> {
+        let player = self.proxy();
+        let volume : f64 = player.get(Self::PLAYER_INTERFACE, "Volume")?;
+        player.set(Self::PLAYER_INTERFACE, "Volume",
+                   (volume + delta).clamp(0., 1.))
+    }
+
+    pub fn raise(&self) -> Result<(), dbus::Error> {
+        self.call(Self::ROOT_INTERFACE, "Raise")
+    }
+
+    pub fn quit(&self) -> Result<(), dbus::Error> {
+        self.call(Self::ROOT_INTERFACE, "Quit")
+    }
+
+    pub fn get_player_info(&self) -> PlayerInfo {
+        let player = self.proxy();
+        let get_string = |prop| {
+            player.get::<String>(Self::ROOT_INTERFACE, prop)
+                  .ok()
+                  .and_then(|s| if s.len() > 0 { Some(s) } else { None })
+        };
+
+        PlayerInfo {
+            identity: get_string("Identity"),
+            desktop_entry: get_string("DesktopEntry"),
+            can_raise: player.get(Self::ROOT_INTERFACE, "CanRaise")
+                             .unwrap_or(false),
+            can_quit: player.get(Self::ROOT_INTERFACE, "CanQuit")
+                            .unwrap_or(false),
+            has_track_list: player.get(Self::ROOT_INTERFACE, "HasTrackList")
+                                  .unwrap_or(false),
+        }
+    }
+
+    pub fn get_capabilities(&self) -> Capabilities {
+        let player = self.proxy();
+        match player.get_all(Self::PLAYER_INTERFACE) {
+            Ok(raw) => Self::parse_capabilities(&raw, Capabilities::default()),
+            Err(err) => {
+                eprintln!("Couldn't get capabilities: {:?}", err);
+                Capabilities::default()
+            },
+        }
+    }
+
+    /// Update `caps` with the `Can*` properties that are present in `raw`
+    fn parse_capabilities(raw : &HashMap<String, arg::Variant<Box<arg::RefArg>>>,
+                          caps: Capabilities) -> Capabilities {
+        let get = |prop, current| {
+            raw.get(prop)
+               .and_then(|v| v.as_i64())
+               .map(|v| v != 0)
+               .unwrap_or(current)
+        };
+
+        Capabilities {
+            can_control: get("CanControl", caps.can_control),
+            can_play: get("CanPlay", caps.can_play),
+            can_pause: get("CanPause", caps.can_pause),
+            can_seek: get("CanSeek", caps.can_seek),
+            can_go_next: get("CanGoNext", caps.can_go_next),
+            can_go_previous: get("CanGoPrevious", caps.can_go_previous),
+        }
+    }
+
+    pub fn get_position(&self) -> Option<i64> {
+        let player = self.proxy();
+        player.get(Self::PLAYER_INTERFACE, "Position").ok()
+    }
+
+    fn send_position(&self) -> Result<(), mpsc::SendError<Event>> {
+        match self.get_position() {
+            Some(position) => self.tx.send(Event::Position(position)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get_status(&self) -> Result<PlaybackStatus, dbus::Error> {
+        let player = self.proxy();
+        let status : String = player.get(Self::PLAYER_INTERFACE,
+                                         "PlaybackStatus")?;
+        PlaybackStatus::from_str(status.as_str()).map_err(|err| {
+            dbus::Error::new_custom("org.freedesktop.DBus.Error.InvalidArgs",
+                                    &err)
+        })
     }
 
-    pub fn get_current(&self) -> Metadata {
-        let player = self.connection.with_path("org.mpris.MediaPlayer2.spotify",
-                                               "/org/mpris/MediaPlayer2", 500);
-        let metadata = player.get("org.mpris.MediaPlayer2.Player", "Metadata")
-                             .unwrap();
+    pub fn get_current(&self) -> Result<Metadata, dbus::Error> {
+        let player = self.proxy();
+        let metadata = player.get(Self::PLAYER_INTERFACE, "Metadata")?;
 
-        Self::parse_metadata(&metadata)
+        Ok(Self::parse_metadata(&metadata))
     }
 
     fn parse_metadata(raw : &HashMap<String, arg::Variant<Box<arg::RefArg>>>)
@@ -148,6 +650,9 @@ impl MPRIS {
             artist: None,
             featured: None,
             art: None,
+            length: None,
+            url: None,
+            lyrics: None,
         };
 
         if let Some(title) = raw.get("xesam:title").and_then(|t| t.as_str()) {
@@ -166,6 +671,19 @@ impl MPRIS {
             }
         }
 
+        if let Some(url) = raw.get("xesam:url").and_then(|u| u.as_str()) {
+            if !url.is_empty() {
+                data.url = Some(url.to_string());
+            }
+        }
+        if let Some(text) = raw.get("xesam:asText").and_then(|t| t.as_str()) {
+            if !text.trim().is_empty() {
+                data.lyrics = Some(text.to_string());
+            }
+        }
+
+        data.length = raw.get("mpris:length").and_then(|l| l.as_i64());
+
         let (artist, featured) = Self::parse_artists(raw);
         data.artist = artist;
         data.featured = featured;
@@ -245,6 +763,9 @@ mod tests {
             artist: Some("Murder By Death".to_string()),
             featured: None,
             art: Some("https://open.spotify.com/image/f568c1436c8a9063d21efdd901e8ce6fdc1029e3".to_string()),
+            length: Some(230853000),
+            url: Some("https://open.spotify.com/track/7tFAnpi9kCBSiNkA6ZPSiZ".to_string()),
+            lyrics: None,
         }, metadata);
     }
 
@@ -279,6 +800,74 @@ mod tests {
                 "Nellie Casman".to_string()
             ]),
             art: Some("https://open.spotify.com/image/7f201a3182356eb97966df061ffc2f38bbe83732".to_string()),
+            length: Some(167933000),
+            url: Some("https://open.spotify.com/track/5IJ7ltnKTfKowtCrVmhN7s".to_string()),
+            lyrics: None,
         }, metadata);
     }
+
+    #[test]
+    fn it_parses_capabilities() {
+        let mut raw : HashMap<String, Variant<Box<RefArg>>> = HashMap::new();
+        raw.insert("CanControl".to_string(), make_variant(true));
+        raw.insert("CanPlay".to_string(), make_variant(true));
+        raw.insert("CanPause".to_string(), make_variant(true));
+        raw.insert("CanSeek".to_string(), make_variant(false));
+        raw.insert("CanGoNext".to_string(), make_variant(true));
+        raw.insert("CanGoPrevious".to_string(), make_variant(false));
+        raw.insert("PlaybackStatus".to_string(), make_variant("Playing".to_string()));
+
+        let caps = MPRIS::parse_capabilities(&raw, Capabilities::default());
+        assert_eq!(Capabilities {
+            can_control: true,
+            can_play: true,
+            can_pause: true,
+            can_seek: false,
+            can_go_next: true,
+            can_go_previous: false,
+        }, caps);
+    }
+
+    #[test]
+    fn it_keeps_unchanged_capabilities() {
+        let mut raw : HashMap<String, Variant<Box<RefArg>>> = HashMap::new();
+        raw.insert("CanGoNext".to_string(), make_variant(false));
+
+        let caps = MPRIS::parse_capabilities(&raw, Capabilities {
+            can_control: true,
+            can_play: true,
+            can_pause: true,
+            can_seek: true,
+            can_go_next: true,
+            can_go_previous: true,
+        });
+        assert_eq!(Capabilities {
+            can_control: true,
+            can_play: true,
+            can_pause: true,
+            can_seek: true,
+            can_go_next: false,
+            can_go_previous: true,
+        }, caps);
+    }
+
+    #[test]
+    fn it_formats_metadata() {
+        let mut data = Metadata {
+            title: Some("Brother".to_string()),
+            album: Some("In Bocca Al Lupo".to_string()),
+            artist: Some("Murder By Death".to_string()),
+            featured: None,
+            art: None,
+            length: None,
+            url: None,
+            lyrics: None,
+        };
+        assert_eq!("Murder By Death - Brother (In Bocca Al Lupo)",
+                   data.to_string());
+
+        data.artist = None;
+        data.album = None;
+        assert_eq!("Brother", data.to_string());
+    }
 }
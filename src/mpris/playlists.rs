@@ -0,0 +1,100 @@
+use super::{MPRIS, Event};
+
+use std::sync::mpsc;
+
+use dbus;
+use dbus::arg;
+use dbus::stdintf::org_freedesktop_dbus::Properties;
+
+/// A playlist from the player's `org.mpris.MediaPlayer2.Playlists`
+#[derive(Debug, PartialEq, Clone, Eq, Hash, Serialize)]
+pub struct Playlist {
+    pub id: String,
+    pub name: String,
+    pub icon: Option<String>,
+}
+
+impl Playlist {
+    fn from_raw(raw: (dbus::Path<'static>, String, String)) -> Self {
+        let (id, name, icon) = raw;
+        Playlist {
+            id: id.to_string(),
+            name,
+            icon: if icon.len() > 0 { Some(icon) } else { None },
+        }
+    }
+}
+
+impl MPRIS {
+    pub(super) const PLAYLISTS_INTERFACE : &'static str =
+        "org.mpris.MediaPlayer2.Playlists";
+    pub(super) const PLAYLISTS_SIGNAL : &'static str =
+        "interface='org.mpris.MediaPlayer2.Playlists',\
+         path='/org/mpris/MediaPlayer2',member='PlaylistChanged'";
+
+    /// How many playlists are fetched from the player
+    const MAX_PLAYLISTS : u32 = 100;
+
+    pub fn get_playlists(&self) -> Result<Vec<Playlist>, dbus::Error> {
+        let player = self.proxy();
+        let mut reply = player.method_call_with_args(
+            &Self::PLAYLISTS_INTERFACE.into(), &"GetPlaylists".into(),
+            |msg| {
+                let mut args = arg::IterAppend::new(msg);
+                args.append(0u32);
+                args.append(Self::MAX_PLAYLISTS);
+                args.append("UserDefined");
+                args.append(false);
+            }
+        )?;
+        let raw : Vec<(dbus::Path<'static>, String, String)> =
+            reply.as_result()?.read1().map_err(|err| {
+                dbus::Error::new_custom("org.freedesktop.DBus.Error.InvalidArgs",
+                                        &format!("{:?}", err))
+            })?;
+
+        Ok(raw.into_iter().map(Playlist::from_raw).collect())
+    }
+
+    pub fn get_active_playlist(&self) -> Option<Playlist> {
+        let player = self.proxy();
+        let (valid, raw) : (bool, (dbus::Path<'static>, String, String)) =
+            player.get(Self::PLAYLISTS_INTERFACE, "ActivePlaylist").ok()?;
+        if valid { Some(Playlist::from_raw(raw)) } else { None }
+    }
+
+    pub fn activate_playlist(&self, id: &str) -> Result<(), dbus::Error> {
+        let id = dbus::Path::new(id).map_err(|err| {
+            dbus::Error::new_custom("org.freedesktop.DBus.Error.InvalidArgs",
+                                    &err)
+        })?;
+        let player = self.proxy();
+        player.method_call_with_args(
+            &Self::PLAYLISTS_INTERFACE.into(), &"ActivatePlaylist".into(),
+            |msg| {
+                arg::IterAppend::new(msg).append(id);
+            }
+        ).map(|_| ())
+    }
+
+    /// Send the playlists to the GUI, if the player has any
+    pub(super) fn load_playlists(&self) -> Result<(), mpsc::SendError<Event>> {
+        match self.get_playlists() {
+            Ok(playlists) => {
+                self.tx.send(Event::Playlists(playlists))?;
+                self.tx.send(Event::ActivePlaylist(self.get_active_playlist()))
+            },
+            // Most players don't implement the Playlists interface
+            Err(_) => Ok(()),
+        }
+    }
+
+    pub(super) fn playlist_changed(&self, sig: &dbus::Message)
+                                   -> Result<(), mpsc::SendError<Event>> {
+        // Renamed playlists may move in the ordering, so just refetch them
+        if sig.get1::<(dbus::Path<'static>, String, String)>().is_some() {
+            self.load_playlists()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,324 @@
+use super::{MPRIS, Metadata, Event};
+use art;
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+
+use dbus;
+use dbus::arg::{self, RefArg};
+use dbus::stdintf::org_freedesktop_dbus::Properties;
+
+/// A track from the player's `org.mpris.MediaPlayer2.TrackList`
+#[derive(Debug, PartialEq, Clone, Eq, Hash, Serialize)]
+pub struct Track {
+    pub id: String,
+    pub data: Metadata,
+}
+
+/// Local copy of the player's track list
+#[derive(Debug, Default)]
+pub struct TrackList {
+    tracks: Vec<Track>,
+    current: Option<String>,
+    /// The art URLs thumbnails were requested for, by track ID
+    thumbnails: HashMap<String, String>,
+}
+
+impl TrackList {
+    /// Object path the spec uses to mean "no track"
+    const NO_TRACK : &'static str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+    pub fn replace(&mut self, tracks: Vec<Track>, current: Option<String>) {
+        self.tracks = tracks;
+        // Players reuse IDs for other tracks
+        self.thumbnails.clear();
+        self.set_current(current);
+    }
+
+    /// Insert a track after the track with ID `after`, or at the start if
+    /// `after` is `NoTrack`
+    pub fn add(&mut self, track: Track, after: &str) {
+        let pos = self.tracks.iter()
+                             .position(|t| t.id == after)
+                             .map(|i| i + 1)
+                             .unwrap_or(0);
+        self.tracks.insert(pos, track);
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        self.tracks.retain(|t| t.id != id);
+    }
+
+    pub fn update(&mut self, id: &str, data: Metadata) {
+        for track in self.tracks.iter_mut().filter(|t| t.id == id) {
+            track.data = data.clone();
+        }
+    }
+
+    pub fn set_current(&mut self, current: Option<String>) {
+        self.current = current.and_then(|c| {
+            if c == Self::NO_TRACK { None } else { Some(c) }
+        });
+    }
+
+    /// The (at most `max`) tracks following the current one
+    pub fn up_next(&self, max: usize) -> Vec<Track> {
+        let start = self.current.as_ref()
+                                .and_then(|c| {
+                                    self.tracks.iter().position(|t| &t.id == c)
+                                })
+                                .map(|i| i + 1)
+                                .unwrap_or(0);
+        self.tracks.iter().skip(start).take(max).cloned().collect()
+    }
+
+    /// The tracks of `up_next` with art whose thumbnails weren't requested
+    /// yet, or whose art changed since. Like the GUI, it forgets the
+    /// thumbnails of tracks that aren't up next anymore.
+    pub fn new_thumbnails(&mut self, up_next: &[Track]) -> Vec<Track> {
+        self.thumbnails.retain(|id, _| up_next.iter().any(|t| &t.id == id));
+        let thumbnails = &mut self.thumbnails;
+        up_next.iter()
+               .filter(|track| match track.data.art {
+                   Some(ref art) => {
+                       let requested = thumbnails.insert(track.id.clone(),
+                                                         art.clone());
+                       requested.as_ref() != Some(art)
+                   },
+                   None => {
+                       thumbnails.remove(&track.id);
+                       false
+                   },
+               })
+               .cloned()
+               .collect()
+    }
+}
+
+impl MPRIS {
+    pub(super) const TRACKLIST_INTERFACE : &'static str =
+        "org.mpris.MediaPlayer2.TrackList";
+    pub(super) const TRACKLIST_SIGNAL : &'static str =
+        "interface='org.mpris.MediaPlayer2.TrackList',\
+         path='/org/mpris/MediaPlayer2'";
+
+    /// How many upcoming tracks are sent to the GUI
+    const UP_NEXT_LEN : usize = 10;
+
+    /// Fetch the whole track list from the player
+    pub fn get_tracklist(&self) -> Result<Vec<Track>, dbus::Error> {
+        let player = self.proxy();
+        let ids : Vec<dbus::Path<'static>> =
+            player.get(Self::TRACKLIST_INTERFACE, "Tracks")?;
+        self.get_tracks_metadata(ids)
+    }
+
+    fn get_tracks_metadata(&self, ids: Vec<dbus::Path<'static>>)
+                           -> Result<Vec<Track>, dbus::Error> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let player = self.proxy();
+        let mut reply = player.method_call_with_args(
+            &Self::TRACKLIST_INTERFACE.into(), &"GetTracksMetadata".into(),
+            |msg| {
+                arg::IterAppend::new(msg).append(ids);
+            }
+        )?;
+        let raw : Vec<HashMap<String, arg::Variant<Box<arg::RefArg>>>> =
+            reply.as_result()?.read1().map_err(|err| {
+                dbus::Error::new_custom("org.freedesktop.DBus.Error.InvalidArgs",
+                                        &format!("{:?}", err))
+            })?;
+
+        Ok(raw.iter().filter_map(Self::parse_track).collect())
+    }
+
+    fn parse_track(raw: &HashMap<String, arg::Variant<Box<arg::RefArg>>>)
+                   -> Option<Track> {
+        raw.get("mpris:trackid")
+           .and_then(|id| id.as_str())
+           .map(|id| Track {
+               id: id.to_string(),
+               data: Self::parse_metadata(raw),
+           })
+    }
+
+    pub fn go_to(&self, id: &str) -> Result<(), dbus::Error> {
+        let id = dbus::Path::new(id).map_err(|err| {
+            dbus::Error::new_custom("org.freedesktop.DBus.Error.InvalidArgs",
+                                    &err)
+        })?;
+        let player = self.proxy();
+        player.method_call_with_args(
+            &Self::TRACKLIST_INTERFACE.into(), &"GoTo".into(), |msg| {
+                arg::IterAppend::new(msg).append(id);
+            }
+        ).map(|_| ())
+    }
+
+    /// The ID of the currently playing track, if the player sets one
+    pub fn get_current_track_id(&self) -> Option<String> {
+        let player = self.proxy();
+        let metadata : HashMap<String, arg::Variant<Box<arg::RefArg>>> =
+            player.get(Self::PLAYER_INTERFACE, "Metadata").ok()?;
+        metadata.get("mpris:trackid")
+                .and_then(|id| id.as_str())
+                .map(|id| id.to_string())
+    }
+
+    /// Load the track list from scratch and send it to the GUI
+    pub(super) fn load_tracklist(&self) -> Result<(), mpsc::SendError<Event>> {
+        match self.get_tracklist() {
+            Ok(tracks) => {
+                self.tracklist.borrow_mut()
+                    .replace(tracks, self.get_current_track_id());
+                self.send_up_next()
+            },
+            Err(err) => {
+                eprintln!("Couldn't get track list: {:?}", err);
+                Ok(())
+            },
+        }
+    }
+
+    /// The current track changed, so the upcoming tracks did as well
+    pub(super) fn current_track_changed(&self)
+                                        -> Result<(), mpsc::SendError<Event>> {
+        let current = self.get_current_track_id();
+        self.tracklist.borrow_mut().set_current(current);
+        self.send_up_next()
+    }
+
+    pub(super) fn tracklist_changed(&self, member: &str, sig: &dbus::Message)
+                                    -> Result<(), mpsc::SendError<Event>> {
+        match member {
+            "TrackListReplaced" => {
+                let (ids, current) =
+                    sig.get2::<Vec<dbus::Path<'static>>, dbus::Path<'static>>();
+                let tracks = ids.map(|ids| self.get_tracks_metadata(ids))
+                                .unwrap_or(Ok(Vec::new()))
+                                .unwrap_or_else(|err| {
+                                    eprintln!("Couldn't get tracks: {:?}", err);
+                                    Vec::new()
+                                });
+                self.tracklist.borrow_mut()
+                    .replace(tracks, current.map(|c| c.to_string()));
+            },
+            "TrackAdded" => {
+                let (raw, after) = sig.get2::<
+                    HashMap<String, arg::Variant<Box<arg::RefArg>>>,
+                    dbus::Path<'static>
+                >();
+                if let (Some(track), Some(after)) =
+                       (raw.as_ref().and_then(Self::parse_track), after) {
+                    self.tracklist.borrow_mut().add(track, &after);
+                }
+            },
+            "TrackRemoved" => {
+                if let Some(id) = sig.get1::<dbus::Path<'static>>() {
+                    self.tracklist.borrow_mut().remove(&id);
+                }
+            },
+            "TrackMetadataChanged" => {
+                let (id, raw) = sig.get2::<
+                    dbus::Path<'static>,
+                    HashMap<String, arg::Variant<Box<arg::RefArg>>>
+                >();
+                if let (Some(id), Some(raw)) = (id, raw) {
+                    self.tracklist.borrow_mut()
+                        .update(&id, Self::parse_metadata(&raw));
+                }
+            },
+            _ => return Ok(()),
+        }
+        self.send_up_next()
+    }
+
+    fn send_up_next(&self) -> Result<(), mpsc::SendError<Event>> {
+        let up_next = self.tracklist.borrow().up_next(Self::UP_NEXT_LEN);
+        let new = self.tracklist.borrow_mut().new_thumbnails(&up_next);
+        for track in &new {
+            self.request_art(art::Request::Thumbnail(
+                track.id.clone(), track.data.clone()
+            ));
+        }
+        self.tx.send(Event::UpNext(up_next))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_track(id: &str) -> Track {
+        Track {
+            id: id.to_string(),
+            data: Metadata {
+                title: Some(id.to_string()),
+                album: None,
+                artist: None,
+                featured: None,
+                art: None,
+                length: None,
+                url: None,
+                lyrics: None,
+            },
+        }
+    }
+
+    fn ids(tracks: &[Track]) -> Vec<&str> {
+        tracks.iter().map(|t| t.id.as_str()).collect()
+    }
+
+    #[test]
+    fn it_lists_tracks_after_the_current_one() {
+        let mut list = TrackList::default();
+        list.replace(vec![
+            make_track("/t/1"), make_track("/t/2"), make_track("/t/3"),
+        ], Some("/t/1".to_string()));
+
+        assert_eq!(vec!["/t/2", "/t/3"], ids(&list.up_next(10)));
+        assert_eq!(vec!["/t/2"], ids(&list.up_next(1)));
+
+        list.set_current(Some(TrackList::NO_TRACK.to_string()));
+        assert_eq!(vec!["/t/1", "/t/2", "/t/3"], ids(&list.up_next(10)));
+    }
+
+    #[test]
+    fn it_adds_and_removes_tracks() {
+        let mut list = TrackList::default();
+        list.replace(vec![make_track("/t/1"), make_track("/t/3")],
+                     Some("/t/1".to_string()));
+
+        list.add(make_track("/t/2"), "/t/1");
+        list.add(make_track("/t/0"), TrackList::NO_TRACK);
+        assert_eq!(vec!["/t/2", "/t/3"], ids(&list.up_next(10)));
+
+        list.remove("/t/1");
+        assert_eq!(vec!["/t/0", "/t/2", "/t/3"], ids(&list.up_next(10)));
+    }
+
+    #[test]
+    fn it_requests_each_thumbnail_once() {
+        let mut list = TrackList::default();
+        let mut tracks = vec![make_track("/t/1"), make_track("/t/2")];
+        assert!(list.new_thumbnails(&tracks).is_empty());
+
+        tracks[0].data.art = Some("file:///tmp/1.png".to_string());
+        tracks[1].data.art = Some("file:///tmp/2.png".to_string());
+        assert_eq!(vec!["/t/1", "/t/2"], ids(&list.new_thumbnails(&tracks)));
+        assert!(list.new_thumbnails(&tracks).is_empty());
+
+        tracks[1].data.art = Some("file:///tmp/cover.png".to_string());
+        tracks.push(make_track("/t/3"));
+        assert_eq!(vec!["/t/2"], ids(&list.new_thumbnails(&tracks)));
+
+        // The thumbnails of tracks that weren't up next in between are gone
+        assert!(list.new_thumbnails(&tracks[..1]).is_empty());
+        assert_eq!(vec!["/t/2"], ids(&list.new_thumbnails(&tracks)));
+
+        list.replace(Vec::new(), None);
+        assert_eq!(2, list.new_thumbnails(&tracks).len());
+    }
+}
@@ -0,0 +1,265 @@
+use headless::Escape;
+use mpris;
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::thread;
+
+use dbus::{self, BusType, Connection, ConnectionItem, Message};
+use dbus::arg::{RefArg, Variant};
+
+const BUS_NAME : &'static str = "org.freedesktop.Notifications";
+const PATH : &'static str = "/org/freedesktop/Notifications";
+const INTERFACE : &'static str = "org.freedesktop.Notifications";
+const ACTION_SIGNAL : &'static str =
+    "type='signal',interface='org.freedesktop.Notifications',\
+     member='ActionInvoked',path='/org/freedesktop/Notifications'";
+
+/// The action keys and labels for the buttons on each notification
+const ACTIONS : &'static [&'static str] = &["next", "Next", "pause", "Pause"];
+
+/// Pixels in the layout of the `image-data` hint
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: i32,
+    pub height: i32,
+    pub rowstride: i32,
+    pub has_alpha: bool,
+    pub bits_per_sample: i32,
+    pub channels: i32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub summary: String,
+    /// In the markup subset of the specification
+    pub body: String,
+    pub image: Option<Image>,
+}
+
+impl Notification {
+    /// A notification about `data` starting to play. `None` if there's no
+    /// title to show.
+    pub fn new(data: &mpris::Metadata, image: Option<Image>) -> Option<Self> {
+        let summary = data.title.clone()?;
+        let body = [&data.artist, &data.album].iter()
+            .filter_map(|part| part.as_ref())
+            .map(|part| Escape::Pango.apply(part))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Some(Notification {
+            summary,
+            body,
+            image,
+        })
+    }
+
+    /// The `Notify` call, replacing the notification `replaces_id` unless
+    /// it's 0
+    fn message(&self, replaces_id: u32) -> Message {
+        let mut hints = HashMap::<&str, Variant<Box<RefArg>>>::new();
+        if let Some(ref image) = self.image {
+            hints.insert("image-data", Variant(Box::new((
+                image.width, image.height, image.rowstride, image.has_alpha,
+                image.bits_per_sample, image.channels, image.data.clone(),
+            ))));
+        }
+        Message::new_method_call(BUS_NAME, PATH, INTERFACE, "Notify").unwrap()
+            .append3("mprvis", replaces_id, "")
+            .append3(&self.summary, &self.body, ACTIONS)
+            .append2(hints, -1i32)
+    }
+}
+
+/// Sends a notification whenever another track starts playing
+pub struct Notifier {
+    pub enabled: bool,
+    commands_tx: mpsc::Sender<mpris::Command>,
+    /// To the thread that talks to the notification server, once it's needed
+    tx: Option<mpsc::Sender<Notification>>,
+    current: Option<mpris::Metadata>,
+    /// A new track we haven't notified about yet, as we're waiting for its
+    /// cover
+    pending: Option<mpris::Metadata>,
+}
+
+impl Notifier {
+    /// A notifier that sends the commands from the action buttons to
+    /// `commands_tx`
+    pub fn new(commands_tx: mpsc::Sender<mpris::Command>) -> Self {
+        Notifier {
+            enabled: false,
+            commands_tx,
+            tx: None,
+            current: None,
+            pending: None,
+        }
+    }
+
+    /// The player sent `data`. If it's another track, the notification is
+    /// sent once its cover is `loaded`.
+    pub fn update(&mut self, data: &mpris::Metadata) {
+        // Players send the metadata again when only the length or art
+        // changed, and we don't notify about what was playing at startup
+        let changed = self.current.as_ref().is_some_and(|current| {
+            (&current.title, &current.artist, &current.album)
+                != (&data.title, &data.artist, &data.album)
+        });
+        if changed {
+            self.pending = Some(data.clone());
+        }
+        self.current = Some(data.clone());
+    }
+
+    /// The cover of the current track was loaded, if there is one
+    pub fn loaded(&mut self, image: Option<Image>) {
+        let data = match self.pending.take() {
+            Some(data) => data,
+            None => return,
+        };
+        if !self.enabled {
+            return;
+        }
+        if let Some(notification) = Notification::new(&data, image) {
+            let commands_tx = self.commands_tx.clone();
+            let tx = self.tx.get_or_insert_with(|| start(commands_tx));
+            if tx.send(notification).is_err() {
+                // Try again with a new thread next time
+                self.tx = None;
+            }
+        }
+    }
+}
+
+/// Start the thread that shows the notifications it receives, replacing the
+/// previous one
+fn start(commands_tx: mpsc::Sender<mpris::Command>)
+         -> mpsc::Sender<Notification> {
+    let (tx, rx) = mpsc::channel::<Notification>();
+    thread::spawn(move || {
+        let connection = match Connection::get_private(BusType::Session) {
+            Ok(connection) => connection,
+            Err(err) => {
+                eprintln!("Couldn't connect to the session bus: {:?}", err);
+                return;
+            },
+        };
+        if let Err(err) = connection.add_match(ACTION_SIGNAL) {
+            eprintln!("Couldn't listen for notification actions: {:?}", err);
+        }
+
+        let mut id = 0;
+        for item in connection.iter(100) {
+            if let ConnectionItem::Signal(sig) = item {
+                if let Some(cmd) = action(&sig, id) {
+                    if commands_tx.send(cmd).is_err() {
+                        return;
+                    }
+                }
+            }
+            loop {
+                let notification = match rx.try_recv() {
+                    Ok(notification) => notification,
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => return,
+                };
+                match notify(&connection, &notification, id) {
+                    Ok(new_id) => id = new_id,
+                    Err(err) => eprintln!("Couldn't show a notification: {}",
+                                          err.message().unwrap_or("")),
+                }
+            }
+        }
+    });
+    tx
+}
+
+fn notify(connection: &Connection, notification: &Notification,
+          replaces_id: u32) -> Result<u32, dbus::Error> {
+    let mut reply = connection.send_with_reply_and_block(
+        notification.message(replaces_id), 500
+    )?;
+    reply.as_result()?.read1().map_err(|err| {
+        dbus::Error::new_custom("org.freedesktop.DBus.Error.InvalidArgs",
+                                &format!("{:?}", err))
+    })
+}
+
+/// The command for an `ActionInvoked` signal about our notification `id`
+fn action(sig: &Message, id: u32) -> Option<mpris::Command> {
+    if sig.member().as_deref() != Some("ActionInvoked") {
+        return None;
+    }
+    match sig.get2::<u32, &str>() {
+        (Some(i), Some("next")) if i == id => Some(mpris::Command::Next),
+        (Some(i), Some("pause")) if i == id => Some(mpris::Command::Pause),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(title: &str, album: Option<&str>) -> mpris::Metadata {
+        mpris::Metadata {
+            title: Some(title.to_string()),
+            album: album.map(|a| a.to_string()),
+            artist: Some("Murder By Death".to_string()),
+            featured: None,
+            art: None,
+            length: None,
+            url: None,
+            lyrics: None,
+        }
+    }
+
+    #[test]
+    fn it_waits_for_real_track_changes() {
+        let (tx, _) = mpsc::channel();
+        let mut notifier = Notifier::new(tx);
+        notifier.update(&track("Brother", None));
+        assert_eq!(None, notifier.pending);
+
+        let mut data = track("Brother", None);
+        data.length = Some(230853000);
+        notifier.update(&data);
+        assert_eq!(None, notifier.pending);
+
+        notifier.update(&track("Brother", Some("In Bocca Al Lupo")));
+        assert!(notifier.pending.is_some());
+        // Disabled, so it's dropped without starting the thread
+        notifier.loaded(None);
+        assert_eq!(None, notifier.pending);
+        assert!(notifier.tx.is_none());
+    }
+
+    #[test]
+    fn it_builds_the_notify_call() {
+        let image = Image {
+            width: 1, height: 1, rowstride: 4, has_alpha: true,
+            bits_per_sample: 8, channels: 4, data: vec![1, 2, 3, 255],
+        };
+        let notification = Notification::new(
+            &track("Brother", Some("Tom & Jerry")), Some(image)
+        ).unwrap();
+        assert_eq!("Murder By Death\nTom &amp; Jerry", notification.body);
+
+        let msg = notification.message(7);
+        assert_eq!(8, msg.get_items().len());
+        let (name, id, _, summary) = msg.get4::<&str, u32, &str, &str>();
+        assert_eq!((Some("mprvis"), Some(7), Some("Brother")),
+                   (name, id, summary));
+        let mut args = msg.iter_init();
+        for _ in 0..6 {
+            args.next();
+        }
+        let hints = args.read::<HashMap<String, Variant<Box<RefArg>>>>()
+                        .unwrap();
+        assert_eq!("(iiibiiay)", &*hints["image-data"].0.signature());
+
+        let no_title = mpris::Metadata { title: None, ..track("", None) };
+        assert_eq!(None, Notification::new(&no_title, None));
+    }
+}
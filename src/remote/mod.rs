@@ -0,0 +1,35 @@
+use std::collections::HashMap;
+
+use dbus;
+use dbus::{Connection, BusType};
+use dbus::arg::{self, Variant};
+
+/// The ID the GUI registers as, which also names its D-Bus object
+pub const APP_ID : &'static str = "space.jazzpis.mprvis";
+const APP_PATH : &'static str = "/space/jazzpis/mprvis";
+const ACTIONS_INTERFACE : &'static str = "org.gtk.Actions";
+
+/// The actions the GUI exports
+pub const ACTIONS : &'static [&'static str] = &[
+    "toggle-fullscreen", "toggle-mini", "next-player", "quit-player",
+    "cycle-layout", "show", "hide", "quit",
+];
+
+/// Activate one of the `ACTIONS` in the running GUI, like
+/// `gapplication action` does. `fullscreen` is short for
+/// `toggle-fullscreen`.
+pub fn activate(action: &str) -> Result<(), dbus::Error> {
+    let action = if action == "fullscreen" { "toggle-fullscreen" }
+                 else { action };
+    let connection = Connection::get_private(BusType::Session)?;
+    let app = connection.with_path(APP_ID, APP_PATH, 500);
+    app.method_call_with_args(
+        &ACTIONS_INTERFACE.into(), &"Activate".into(), |msg| {
+            let mut args = arg::IterAppend::new(msg);
+            args.append(action);
+            // No parameter and no platform data
+            args.append(Vec::<Variant<bool>>::new());
+            args.append(HashMap::<&str, Variant<bool>>::new());
+        }
+    ).map(|_| ())
+}
@@ -0,0 +1,474 @@
+use mpris;
+use notify;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::process;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use dbus::{self, BusType, Connection, ConnectionItem, Message};
+use dbus::arg::{RefArg, Variant};
+use dbus::tree::{Factory, MethodErr};
+
+const ITEM_PATH : &'static str = "/StatusNotifierItem";
+const ITEM_INTERFACE : &'static str = "org.kde.StatusNotifierItem";
+const MENU_PATH : &'static str = "/MenuBar";
+const MENU_INTERFACE : &'static str = "com.canonical.dbusmenu";
+const WATCHER : &'static str = "org.kde.StatusNotifierWatcher";
+/// Tells us when the tray (re)starts, so we can register again
+const WATCHER_SIGNAL : &'static str =
+    "type='signal',interface='org.freedesktop.DBus',\
+     member='NameOwnerChanged',arg0='org.kde.StatusNotifierWatcher'";
+
+/// Numbers the items for their bus names
+static ITEMS : AtomicUsize = AtomicUsize::new(0);
+
+/// How much one step of the mouse wheel changes the volume
+const VOLUME_STEP : f64 = 0.05;
+
+/// What the tray needs to know about the player and the window
+#[derive(Debug, Clone)]
+pub enum Update {
+    Data(mpris::Metadata),
+    /// The cover of the current track
+    Icon(Option<notify::Image>),
+    Playback(mpris::PlaybackStatus),
+    Capabilities(mpris::Capabilities),
+    WindowShown(bool),
+}
+
+/// What the tray asks of the window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    ToggleWindow,
+    Quit,
+}
+
+type Pixmap = (i32, i32, Vec<u8>);
+type Properties = HashMap<String, Variant<Box<RefArg>>>;
+/// An entry and its children in `GetLayout`
+type Layout = (i32, Properties, Vec<Variant<Box<RefArg>>>);
+/// An entry's ID, the event, its data and when it happened
+type Event<'a> = (i32, &'a str, Variant<Box<RefArg>>, u32);
+
+#[derive(Debug, Default)]
+struct State {
+    /// The title and the description in the markup subset
+    tooltip: Option<(String, String)>,
+    icon: Vec<Pixmap>,
+    playing: bool,
+    capabilities: mpris::Capabilities,
+    window_shown: bool,
+    /// Counts the menu changes
+    revision: u32,
+}
+
+impl State {
+    fn update(&mut self, update: Update) {
+        match update {
+            Update::Data(data) => {
+                self.tooltip = notify::Notification::new(&data, None)
+                    .map(|n| (n.summary, n.body));
+            },
+            Update::Icon(image) => {
+                self.icon = image.iter().map(pixmap).collect();
+            },
+            Update::Playback(status) => {
+                self.playing = status == mpris::PlaybackStatus::Playing;
+                self.revision += 1;
+            },
+            Update::Capabilities(capabilities) => {
+                self.capabilities = capabilities;
+                self.revision += 1;
+            },
+            Update::WindowShown(shown) => {
+                self.window_shown = shown;
+                self.revision += 1;
+            },
+        }
+    }
+}
+
+/// The icon in the ARGB32 layout of `IconPixmap`, in network byte order
+fn pixmap(image: &notify::Image) -> Pixmap {
+    let channels = image.channels as usize;
+    let mut data = Vec::with_capacity(
+        (image.width * image.height * 4) as usize
+    );
+    for y in 0..image.height as usize {
+        let row = &image.data[y * image.rowstride as usize..];
+        for pixel in row.chunks(channels).take(image.width as usize) {
+            let alpha = if image.has_alpha { pixel[3] } else { 0xff };
+            data.extend_from_slice(&[alpha, pixel[0], pixel[1], pixel[2]]);
+        }
+    }
+    (image.width, image.height, data)
+}
+
+/// The entries of the context menu, by their dbusmenu ID
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Item {
+    PlayPause = 1,
+    Previous,
+    Next,
+    Separator,
+    Window,
+    Quit,
+}
+
+impl Item {
+    const ALL : &'static [Item] = &[
+        Item::PlayPause, Item::Previous, Item::Next, Item::Separator,
+        Item::Window, Item::Quit,
+    ];
+
+    fn from_id(id: i32) -> Option<Item> {
+        Self::ALL.iter().cloned().find(|&item| item as i32 == id)
+    }
+
+    fn properties(&self, state: &State) -> Properties {
+        let caps = &state.capabilities;
+        let (label, enabled) = match *self {
+            Item::PlayPause if state.playing => ("Pause", caps.can_pause),
+            Item::PlayPause => ("Play", caps.can_play),
+            Item::Previous => ("Previous", caps.can_go_previous),
+            Item::Next => ("Next", caps.can_go_next),
+            Item::Separator => {
+                let mut properties = Properties::new();
+                properties.insert("type".to_string(),
+                                  variant("separator".to_string()));
+                return properties;
+            },
+            Item::Window if state.window_shown => ("Hide window", true),
+            Item::Window => ("Show window", true),
+            Item::Quit => ("Quit", true),
+        };
+        let needs_control = !matches!(*self, Item::Window | Item::Quit);
+        let mut properties = Properties::new();
+        properties.insert("label".to_string(), variant(label.to_string()));
+        properties.insert("enabled".to_string(),
+                          variant(enabled && (caps.can_control
+                                              || !needs_control)));
+        properties
+    }
+}
+
+fn variant<T: RefArg + 'static>(value: T) -> Variant<Box<RefArg>> {
+    Variant(Box::new(value))
+}
+
+/// The menu layout below `id`, as returned by `GetLayout`
+fn layout(state: &State, id: i32) -> Layout {
+    match Item::from_id(id) {
+        Some(item) => (id, item.properties(state), Vec::new()),
+        None => {
+            let mut properties = Properties::new();
+            properties.insert("children-display".to_string(),
+                              variant("submenu".to_string()));
+            let children = Item::ALL.iter()
+                .map(|&item| variant(layout(state, item as i32)))
+                .collect();
+            (0, properties, children)
+        },
+    }
+}
+
+/// Export a `StatusNotifierItem` until the returned sender is dropped. The
+/// playback buttons send to `commands_tx`, everything else goes through
+/// `on_action`.
+pub fn start(commands_tx: mpsc::Sender<mpris::Command>,
+             on_action: Box<Fn(Action) + Send>) -> mpsc::Sender<Update> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        if let Err(err) = run(&rx, commands_tx, on_action) {
+            eprintln!("Couldn't show the tray icon: {}",
+                      err.message().unwrap_or("Unknown error"));
+        }
+    });
+    tx
+}
+
+fn run(rx: &mpsc::Receiver<Update>,
+       commands_tx: mpsc::Sender<mpris::Command>,
+       on_action: Box<Fn(Action) + Send>) -> Result<(), dbus::Error> {
+    let connection = Connection::get_private(BusType::Session)?;
+    // The specification wants a name per item, and the previous one might
+    // still be around when the tray is turned off and on again
+    let name = format!("org.kde.StatusNotifierItem-{}-{}", process::id(),
+                       ITEMS.fetch_add(1, Ordering::SeqCst) + 1);
+    connection.register_name(&name, 0)?;
+
+    let state = Rc::new(RefCell::new(State::default()));
+    let volume_tx = commands_tx.clone();
+    let activate = {
+        let state = state.clone();
+        move |item: Item| {
+            let command = match item {
+                Item::PlayPause => mpris::Command::PlayPause,
+                Item::Previous => mpris::Command::Previous,
+                Item::Next => mpris::Command::Next,
+                Item::Separator => return,
+                Item::Window => return on_action(Action::ToggleWindow),
+                Item::Quit => return on_action(Action::Quit),
+            };
+            if state.borrow().capabilities.can_control {
+                let _ = commands_tx.send(command);
+            }
+        }
+    };
+    let activate = Rc::new(activate);
+
+    let f = Factory::new_fn::<()>();
+    let item = {
+        let (s1, s2, s3) = (state.clone(), state.clone(), state.clone());
+        let (a1, a2) = (activate.clone(), activate.clone());
+        f.interface(ITEM_INTERFACE, ())
+            .add_p(f.property::<&str, _>("Category", ())
+                    .on_get(|i, _| { i.append("ApplicationStatus"); Ok(()) }))
+            .add_p(f.property::<&str, _>("Id", ())
+                    .on_get(|i, _| { i.append("mprvis"); Ok(()) }))
+            .add_p(f.property::<&str, _>("Title", ())
+                    .on_get(|i, _| { i.append("MPRVis"); Ok(()) }))
+            .add_p(f.property::<&str, _>("Status", ())
+                    .on_get(|i, _| { i.append("Active"); Ok(()) }))
+            .add_p(f.property::<bool, _>("ItemIsMenu", ())
+                    .on_get(|i, _| { i.append(false); Ok(()) }))
+            .add_p(f.property::<dbus::Path, _>("Menu", ())
+                    .on_get(|i, _| {
+                        i.append(dbus::Path::from(MENU_PATH));
+                        Ok(())
+                    }))
+            .add_p(f.property::<&str, _>("IconName", ())
+                    .on_get(move |i, _| {
+                        // Hosts prefer the name over the pixmap
+                        let cover = !s1.borrow().icon.is_empty();
+                        i.append(if cover { "" } else { "audio-x-generic" });
+                        Ok(())
+                    }))
+            .add_p(f.property::<Vec<Pixmap>, _>("IconPixmap", ())
+                    .on_get(move |i, _| {
+                        i.append(&s2.borrow().icon);
+                        Ok(())
+                    }))
+            .add_p(f.property::<(&str, Vec<Pixmap>, &str, &str), _>(
+                        "ToolTip", ())
+                    .on_get(move |i, _| {
+                        let state = s3.borrow();
+                        let (title, description) = match state.tooltip {
+                            Some((ref title, ref description)) =>
+                                (title.as_str(), description.as_str()),
+                            None => ("Nothing playing", ""),
+                        };
+                        i.append(("", &state.icon, title, description));
+                        Ok(())
+                    }))
+            .add_m(f.method("Activate", (), move |m| {
+                a1(Item::Window);
+                Ok(vec![m.msg.method_return()])
+            }).inarg::<i32, _>("x").inarg::<i32, _>("y"))
+            .add_m(f.method("SecondaryActivate", (), move |m| {
+                a2(Item::PlayPause);
+                Ok(vec![m.msg.method_return()])
+            }).inarg::<i32, _>("x").inarg::<i32, _>("y"))
+            .add_m(f.method("Scroll", (), move |m| {
+                let (delta, orientation): (i32, &str) = m.msg.read2()?;
+                if orientation.eq_ignore_ascii_case("vertical") && delta != 0 {
+                    let step = VOLUME_STEP * delta.signum() as f64;
+                    let _ = volume_tx.send(mpris::Command::ChangeVolume(step));
+                }
+                Ok(vec![m.msg.method_return()])
+            }).inarg::<i32, _>("delta").inarg::<&str, _>("orientation"))
+    };
+
+    let menu = {
+        let (s1, s2, s3) = (state.clone(), state.clone(), state.clone());
+        let (a1, a2) = (activate.clone(), activate.clone());
+        f.interface(MENU_INTERFACE, ())
+            .add_p(f.property::<u32, _>("Version", ())
+                    .on_get(|i, _| { i.append(3u32); Ok(()) }))
+            .add_p(f.property::<&str, _>("TextDirection", ())
+                    .on_get(|i, _| { i.append("ltr"); Ok(()) }))
+            .add_p(f.property::<&str, _>("Status", ())
+                    .on_get(|i, _| { i.append("normal"); Ok(()) }))
+            .add_p(f.property::<Vec<&str>, _>("IconThemePath", ())
+                    .on_get(|i, _| { i.append(Vec::<&str>::new()); Ok(()) }))
+            .add_m(f.method("GetLayout", (), move |m| {
+                let (parent, _): (i32, i32) = m.msg.read2()?;
+                let state = s1.borrow();
+                Ok(vec![m.msg.method_return()
+                         .append2(state.revision, layout(&state, parent))])
+            }).inarg::<i32, _>("parentId").inarg::<i32, _>("recursionDepth")
+              .inarg::<Vec<&str>, _>("propertyNames")
+              .outarg::<u32, _>("revision")
+              .outarg::<Layout, _>("layout"))
+            .add_m(f.method("GetGroupProperties", (), move |m| {
+                let ids: Vec<i32> = m.msg.read1()?;
+                let state = s2.borrow();
+                let items : Vec<_> = ids.into_iter()
+                    .filter_map(|id| {
+                        Item::from_id(id).map(|item| {
+                            (id, item.properties(&state))
+                        })
+                    })
+                    .collect();
+                Ok(vec![m.msg.method_return().append1(items)])
+            }).inarg::<Vec<i32>, _>("ids")
+              .inarg::<Vec<&str>, _>("propertyNames")
+              .outarg::<Vec<(i32, Properties)>, _>("properties"))
+            .add_m(f.method("GetProperty", (), move |m| {
+                let (id, name): (i32, &str) = m.msg.read2()?;
+                let item = Item::from_id(id).ok_or_else(|| {
+                    MethodErr::invalid_arg(&id)
+                })?;
+                let mut properties = item.properties(&s3.borrow());
+                let value = properties.remove(name).ok_or_else(|| {
+                    MethodErr::invalid_arg(&name)
+                })?;
+                Ok(vec![m.msg.method_return().append1(value)])
+            }).inarg::<i32, _>("id").inarg::<&str, _>("name")
+              .outarg::<Variant<Box<RefArg>>, _>("value"))
+            .add_m(f.method("Event", (), move |m| {
+                let (id, event): (i32, &str) = m.msg.read2()?;
+                if event == "clicked" {
+                    if let Some(item) = Item::from_id(id) {
+                        a1(item);
+                    }
+                }
+                Ok(vec![m.msg.method_return()])
+            }).inarg::<i32, _>("id").inarg::<&str, _>("eventId")
+              .inarg::<Variant<Box<RefArg>>, _>("data")
+              .inarg::<u32, _>("timestamp"))
+            .add_m(f.method("EventGroup", (), move |m| {
+                let events: Vec<Event> = m.msg.read1()?;
+                for (id, event, _, _) in events {
+                    if event == "clicked" {
+                        if let Some(item) = Item::from_id(id) {
+                            a2(item);
+                        }
+                    }
+                }
+                Ok(vec![m.msg.method_return().append1(Vec::<i32>::new())])
+            }).inarg::<Vec<Event>, _>("events")
+              .outarg::<Vec<i32>, _>("idErrors"))
+            .add_m(f.method("AboutToShow", (), |m| {
+                Ok(vec![m.msg.method_return().append1(false)])
+            }).inarg::<i32, _>("id").outarg::<bool, _>("needUpdate"))
+            .add_m(f.method("AboutToShowGroup", (), |m| {
+                Ok(vec![m.msg.method_return()
+                         .append2(Vec::<i32>::new(), Vec::<i32>::new())])
+            }).inarg::<Vec<i32>, _>("ids")
+              .outarg::<Vec<i32>, _>("updatesNeeded")
+              .outarg::<Vec<i32>, _>("idErrors"))
+    };
+
+    let tree = f.tree(())
+        .add(f.object_path(ITEM_PATH, ()).introspectable().add(item))
+        .add(f.object_path(MENU_PATH, ()).introspectable().add(menu));
+    tree.set_registered(&connection, true)?;
+    connection.add_handler(tree);
+
+    connection.add_match(WATCHER_SIGNAL)?;
+    register(&connection, &name);
+
+    for item in connection.iter(100) {
+        if let ConnectionItem::Signal(sig) = item {
+            // The tray was (re)started
+            if let (_, _, Some(owner)) = sig.get3::<&str, &str, &str>() {
+                if !owner.is_empty() {
+                    register(&connection, &name);
+                }
+            }
+        }
+        loop {
+            let update = match rx.try_recv() {
+                Ok(update) => update,
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => return Ok(()),
+            };
+            let signals : &[&str] = match update {
+                Update::Data(_) => &["NewToolTip"],
+                Update::Icon(_) => &["NewIcon", "NewToolTip"],
+                _ => &[],
+            };
+            let revision = state.borrow().revision;
+            state.borrow_mut().update(update);
+            for signal in signals {
+                let msg = Message::new_signal(ITEM_PATH, ITEM_INTERFACE,
+                                              *signal).unwrap();
+                let _ = connection.send(msg);
+            }
+            let new_revision = state.borrow().revision;
+            if new_revision != revision {
+                let msg = Message::new_signal(MENU_PATH, MENU_INTERFACE,
+                                              "LayoutUpdated").unwrap()
+                    .append2(new_revision, 0i32);
+                let _ = connection.send(msg);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Tell the tray about our item
+fn register(connection: &Connection, name: &str) {
+    let watcher = connection.with_path(WATCHER, "/StatusNotifierWatcher", 500);
+    let result = watcher.method_call_with_args(
+        &WATCHER.into(), &"RegisterStatusNotifierItem".into(), |msg| {
+            dbus::arg::IterAppend::new(msg).append(name);
+        }
+    );
+    if result.is_err() {
+        eprintln!("No system tray is running, the icon will show up once \
+                   there is one");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_converts_icons_to_argb() {
+        let image = notify::Image {
+            width: 1, height: 2, rowstride: 4, has_alpha: false,
+            bits_per_sample: 8, channels: 3,
+            data: vec![1, 2, 3, 0, 4, 5, 6],
+        };
+        assert_eq!((1, 2, vec![0xff, 1, 2, 3, 0xff, 4, 5, 6]), pixmap(&image));
+    }
+
+    #[test]
+    fn it_labels_the_menu_for_the_state() {
+        let label = |item: Item, state: &State| {
+            let properties = item.properties(state);
+            (properties["label"].0.as_str().unwrap().to_string(),
+             properties["enabled"].0.as_i64() == Some(1))
+        };
+        let mut state = State::default();
+        assert_eq!(("Play".to_string(), false),
+                   label(Item::PlayPause, &state));
+        assert_eq!(("Show window".to_string(), true),
+                   label(Item::Window, &state));
+
+        state.update(Update::Capabilities(mpris::Capabilities {
+            can_control: true,
+            can_pause: true,
+            ..Default::default()
+        }));
+        state.update(Update::Playback(mpris::PlaybackStatus::Playing));
+        state.update(Update::WindowShown(true));
+        assert_eq!(("Pause".to_string(), true),
+                   label(Item::PlayPause, &state));
+        assert_eq!(("Next".to_string(), false), label(Item::Next, &state));
+        assert_eq!(("Hide window".to_string(), true),
+                   label(Item::Window, &state));
+        assert_eq!(3, state.revision);
+
+        let (id, _, children) = layout(&state, 0);
+        assert_eq!((0, Item::ALL.len()), (id, children.len()));
+        assert_eq!(Some(Item::Quit), Item::from_id(6));
+    }
+}
@@ -0,0 +1,41 @@
+//! The XDG base directories, from GLib if we have it
+
+use std::path::PathBuf;
+#[cfg(not(feature = "gui"))]
+use std::env;
+
+#[cfg(feature = "gui")]
+use glib;
+
+/// `$XDG_CACHE_HOME/mprvis`
+#[cfg(feature = "gui")]
+pub fn cache_dir() -> Option<PathBuf> {
+    glib::get_user_cache_dir().map(|cache| cache.join("mprvis"))
+}
+
+/// `$XDG_CACHE_HOME/mprvis`
+#[cfg(not(feature = "gui"))]
+pub fn cache_dir() -> Option<PathBuf> {
+    base_dir("XDG_CACHE_HOME", ".cache").map(|cache| cache.join("mprvis"))
+}
+
+/// `$XDG_CONFIG_HOME/mprvis`
+#[cfg(feature = "gui")]
+pub fn config_dir() -> Option<PathBuf> {
+    glib::get_user_config_dir().map(|config| config.join("mprvis"))
+}
+
+/// `$XDG_CONFIG_HOME/mprvis`
+#[cfg(not(feature = "gui"))]
+pub fn config_dir() -> Option<PathBuf> {
+    base_dir("XDG_CONFIG_HOME", ".config").map(|config| config.join("mprvis"))
+}
+
+/// The directory in `var`, or `fallback` in the home directory
+#[cfg(not(feature = "gui"))]
+fn base_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    match env::var_os(var) {
+        Some(ref dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)),
+    }
+}